@@ -5,3 +5,29 @@ pub const BALANCE_MAX_SUPPLY: Uint128 = Uint128::new(21_000_000_000_000u128);
 
 // Juno max supply - 6 decimals
 pub const JUNO_MAX_SUPPLY: Uint128 = Uint128::new(185_562_268_000_000u128);
+
+// Rolling windows are kept in buckets of 1/24th of the window
+pub const WINDOW_BUCKETS: u64 = 24;
+
+// Longest vesting cliff or duration and burn crank interval - ten years, in seconds
+pub const MAX_DURATION: u64 = 10 * 365 * 24 * 3_600;
+
+// Pagination
+pub const DEFAULT_LIMIT: u32 = 10;
+pub const MAX_LIMIT: u32 = 30;
+
+// Reply ids - payouts use the ids from `PAYOUT_REPLY_ID_START`
+pub const CREATE_DENOM_REPLY_ID: u64 = 1;
+pub const IBC_FORWARD_REPLY_ID: u64 = 2;
+pub const PAYOUT_REPLY_ID_START: u64 = 100;
+
+// ibc-hooks - the funds of a wasm memo are sent by an address derived from the channel and sender
+pub const BECH32_PREFIX: &str = "juno";
+pub const IBC_HOOKS_SENDER_PREFIX: &str = "ibc-wasm-hook-intermediary";
+
+// Denom admin renounced to the address of 20 zero bytes, no key hashes to it
+pub const RENOUNCED_ADMIN: &str = "juno1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq93ryqp";
+
+// ICS-20 transfers sent as a stargate message, with the ibc-hooks callback memo
+pub const MSG_TRANSFER_TYPE_URL: &str = "/ibc.applications.transfer.v1.MsgTransfer";
+pub const TRANSFER_PORT: &str = "transfer";
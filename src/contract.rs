@@ -1,15 +1,42 @@
 use cosmwasm_std::{
-    ensure, entry_point, to_binary, Binary, Coin, Deps, DepsMut, Env, MessageInfo, Response,
-    StdResult, Uint128,
+    ensure, entry_point, to_binary, Binary, Coin, Deps, DepsMut, Env, Event, MessageInfo, Order,
+    QueryRequest, Reply, Response, StdError, StdResult, SubMsg, Uint128,
 };
-use cw2::set_contract_version;
-use token_bindings::{TokenFactoryMsg, TokenFactoryQuery};
+use cw2::{get_contract_version, set_contract_version};
+use cw_storage_plus::Bound;
+use cw_utils::nonpayable;
+use token_bindings::{AdminResponse, TokenFactoryMsg, TokenFactoryQuery};
 
+use crate::constants::{
+    BECH32_PREFIX, CREATE_DENOM_REPLY_ID, DEFAULT_LIMIT, IBC_FORWARD_REPLY_ID, MAX_LIMIT,
+    PAYOUT_REPLY_ID_START,
+};
 use crate::error::ContractError;
-use crate::executes::swap;
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
+use crate::executes::{
+    burn, burn_from, change_denom_admin, claim, claim_vested, create_denom_reply, create_product,
+    execute_finalize, fund_reserve, grant_role, ibc_forward_reply, ibc_lifecycle_complete, pause,
+    payout_reply, receive_cw20, recover_funds, redeem, reset_circuit_breaker, revoke_role,
+    set_denom_metadata, settle_snapshot_debt, swap, unpause, update_burn_crank,
+    update_burn_destination, update_circuit_breaker, update_cw20_allowlist, update_lockup,
+    update_redemption, update_referral, update_snapshot_settlement, update_vesting, withdraw,
+    withdraw_reserve,
+};
+use crate::helpers::{
+    check_invariants, default_product, derive_intermediate_sender, ensure_role, has_role,
+    is_minting_finished, is_paused, load_product, query_redemption_status, query_supply_info,
+    recoverable_amount, simulate_swap,
+};
+use crate::migrations::{parse_version, run_migrations};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, SudoMsg};
 use crate::state::{
-    BurnedSnapshot, Config, Statistics, BURNED_REMINTED_SNAPSHOT, CONFIG, STATS, TO_BURN,
+    factory_denom, AddressSwapped, BurnCrank, BurnDestination, BurnRecord, CircuitBreakerStatus,
+    Config, Lifecycle, Lock, Lockup, Operation, OperationStatus, Phase, Product, Referral,
+    ReferrerStats, Role, RoleMembers, SnapshotDebt, SnapshotSettlement, Statistics, SwapLimits,
+    SwapVolume, Vesting, VestingPosition, BURNED_REMINTED_SNAPSHOT, BURN_CRANK, BURN_DESTINATION,
+    BURN_HISTORY, CIRCUIT_BREAKER, CLAIMS, CONFIG, CW20_ALLOWLIST, CW20_STATS, DENOM_METADATA,
+    FINALIZED_AT, LOCKS, LOCKUP, PAUSED, PENDING_DENOM, PRODUCTS, REFERRAL, REFERRERS,
+    REFERRERS_BY_VOLUME, ROLES, SNAPSHOT_SETTLEMENT, STATS, SWAPPED_BY_ADDRESS, SWAP_LIMITS,
+    SWAP_VOLUME, TO_BURN, VESTING, VESTING_POSITIONS,
 };
 
 pub const CONTRACT_NAME: &str = "crates.io:balance-token-swap";
@@ -24,29 +51,76 @@ pub fn instantiate(
 ) -> Result<Response<TokenFactoryMsg>, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-    let config = Config::validate(
-        info.sender.as_ref(),
-        deps.branch(),
-        init_msg,
-        format!("factory/{}/{}", env.contract.address, "balance"),
-    )?;
+    let swap_limits = init_msg
+        .swap_limits
+        .clone()
+        .unwrap_or_else(SwapLimits::unlimited);
+    swap_limits.validate()?;
+
+    let circuit_breaker = init_msg
+        .circuit_breaker
+        .clone()
+        .map(|circuit_breaker| circuit_breaker.validate(deps.api))
+        .transpose()?;
+
+    let burn_destination = init_msg
+        .burn_destination
+        .clone()
+        .unwrap_or(BurnDestination::Hold)
+        .validate(deps.api)?;
+
+    let subdenom = init_msg.subdenom.clone();
+    let factory_denom = factory_denom(&env.contract.address, &subdenom)?;
+
+    let denom_metadata = init_msg
+        .denom_metadata
+        .clone()
+        .map(|denom_metadata| denom_metadata.validate(&factory_denom))
+        .transpose()?;
+
+    let config = Config::validate(info.sender.as_ref(), deps.branch(), init_msg, factory_denom)?;
 
     CONFIG.save(deps.storage, &config)?;
 
     STATS.save(deps.storage, &Statistics::zero())?;
 
+    PRODUCTS.save(
+        deps.storage,
+        &config.factory_denom,
+        &default_product(&config, &subdenom, Uint128::zero()),
+    )?;
+
+    SWAP_LIMITS.save(deps.storage, &swap_limits)?;
+
+    BURN_DESTINATION.save(deps.storage, &burn_destination)?;
+
+    if let Some(circuit_breaker) = circuit_breaker {
+        CIRCUIT_BREAKER.save(deps.storage, &circuit_breaker)?;
+    }
+    SWAP_VOLUME.save(deps.storage, &SwapVolume::zero())?;
+
     TO_BURN.save(
         deps.storage,
         &Coin {
-            denom: config.accepted_denom,
+            denom: config.accepted_denom.clone(),
             amount: Uint128::zero(),
         },
     )?;
 
-    Ok(Response::new().add_message(TokenFactoryMsg::CreateDenom {
-        subdenom: "balance".to_string(),
-        metadata: None, // exponent 6
-    }))
+    let metadata = denom_metadata
+        .as_ref()
+        .map(|denom_metadata| denom_metadata.to_token_factory(&config.factory_denom));
+    if let Some(denom_metadata) = denom_metadata {
+        DENOM_METADATA.save(deps.storage, &denom_metadata)?;
+    }
+
+    // Confirmed in `reply` with the denom actually created
+    PENDING_DENOM.save(deps.storage, &config.factory_denom)?;
+
+    Ok(Response::new().add_submessage(SubMsg::reply_on_success(
+        TokenFactoryMsg::CreateDenom { subdenom, metadata },
+        CREATE_DENOM_REPLY_ID,
+    )))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -56,68 +130,514 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    // Only swapping, settling the snapshot debt and the redemptions take funds
+    if !matches!(
+        msg,
+        ExecuteMsg::Swap { .. }
+            | ExecuteMsg::SettleSnapshotDebt {}
+            | ExecuteMsg::FundReserve {}
+            | ExecuteMsg::Redeem {}
+    ) {
+        nonpayable(&info).map_err(|_| ContractError::NonPayable {})?;
+    }
     match msg {
-        ExecuteMsg::Swap {} => {
+        ExecuteMsg::Swap {
+            product,
+            referrer,
+            lock_duration,
+            recipient,
+            forward_ibc,
+        } => {
             let config = CONFIG.load(deps.storage)?;
-            ensure!(config.enabled, ContractError::SwapDisabled {});
-            swap(deps, _env, info)
-        }
-        ExecuteMsg::EnableDisable {} => {
-            // Alts swapping
-            let mut config = CONFIG.load(deps.storage)?;
             ensure!(
-                info.sender.as_str() == config.contract_owner,
-                ContractError::Unauthorized {}
+                config.enabled && !is_paused(deps.storage, Operation::Swap),
+                ContractError::SwapDisabled {}
             );
-            config.enabled = !config.enabled;
-            CONFIG.save(deps.storage, &config)?;
+            swap(
+                deps,
+                _env,
+                info,
+                product,
+                referrer,
+                lock_duration,
+                recipient,
+                forward_ibc,
+            )
+        }
+        // Deprecated alias of `Pause` / `Unpause` on swapping
+        ExecuteMsg::EnableDisable {} => {
+            let config = CONFIG.load(deps.storage)?;
+            if config.enabled && !is_paused(deps.storage, Operation::Swap) {
+                pause(deps, _env, info, Some(vec![Operation::Swap]), None)
+            } else {
+                unpause(deps, info, Some(vec![Operation::Swap]))
+            }
+        }
+        // Not implemented until the burn crank is enabled with the new burn module
+        ExecuteMsg::Burn {} => {
+            let config = CONFIG.load(deps.storage)?;
+            ensure_role(deps.storage, &config, &info.sender, Role::Burner)?;
+            burn(deps, _env, info, false)
+        }
+        ExecuteMsg::UpdateSwapLimits { swap_limits } => {
+            let config = CONFIG.load(deps.storage)?;
+            ensure_role(deps.storage, &config, &info.sender, Role::ConfigManager)?;
+            swap_limits.validate()?;
+            SWAP_LIMITS.save(deps.storage, &swap_limits)?;
             Ok(Response::default())
         }
-        // Will be changed when the new burn module is live
-        ExecuteMsg::Burn {} => Err(ContractError::NotImplementedYet {}),
+        ExecuteMsg::UpdateCircuitBreaker { circuit_breaker } => {
+            update_circuit_breaker(deps, info, circuit_breaker)
+        }
+        ExecuteMsg::ResetCircuitBreaker {} => reset_circuit_breaker(deps, info),
+        ExecuteMsg::GrantRole { role, address } => grant_role(deps, info, role, address),
+        ExecuteMsg::RevokeRole { role, address } => revoke_role(deps, info, role, address),
+        ExecuteMsg::Pause { operations, reason } => pause(deps, _env, info, operations, reason),
+        ExecuteMsg::Unpause { operations } => unpause(deps, info, operations),
+        ExecuteMsg::SetDenomMetadata { metadata } => set_denom_metadata(deps, info, metadata),
+        ExecuteMsg::CreateProduct {
+            subdenom,
+            rate,
+            cap,
+            metadata,
+        } => create_product(deps, _env, info, subdenom, rate, cap, metadata),
+        ExecuteMsg::ChangeDenomAdmin { denom, new_admin } => {
+            change_denom_admin(deps, _env, info, denom, new_admin)
+        }
+        ExecuteMsg::Finalize {} => execute_finalize(deps, _env),
+        ExecuteMsg::Claim {} => claim(deps, info),
+        ExecuteMsg::RecoverFunds { denom, amount, to } => {
+            recover_funds(deps, _env, info, denom, amount, to)
+        }
+        ExecuteMsg::UpdateBurnCrank { burn_crank } => update_burn_crank(deps, info, burn_crank),
+        ExecuteMsg::UpdateReferral { referral } => update_referral(deps, info, referral),
+        ExecuteMsg::UpdateVesting { vesting } => update_vesting(deps, info, vesting),
+        ExecuteMsg::ClaimVested {} => claim_vested(deps, _env, info),
+        ExecuteMsg::UpdateLockup { lockup } => update_lockup(deps, info, lockup),
+        ExecuteMsg::Withdraw {} => withdraw(deps, _env, info),
+        ExecuteMsg::UpdateRedemption { redemption } => update_redemption(deps, info, redemption),
+        ExecuteMsg::FundReserve {} => fund_reserve(deps, info),
+        ExecuteMsg::WithdrawReserve { amount, to } => withdraw_reserve(deps, info, amount, to),
+        ExecuteMsg::Redeem {} => redeem(deps, _env, info),
+        ExecuteMsg::Receive(cw20_msg) => receive_cw20(deps, _env, info, cw20_msg),
+        ExecuteMsg::UpdateCw20Allowlist { add, remove } => {
+            update_cw20_allowlist(deps, info, add, remove)
+        }
+        ExecuteMsg::CrankBurn {} => burn(deps, _env, info, true),
+        ExecuteMsg::SettleSnapshotDebt {} => settle_snapshot_debt(deps, _env, info),
+        ExecuteMsg::UpdateSnapshotSettlement { enabled } => {
+            update_snapshot_settlement(deps, info, enabled)
+        }
+        ExecuteMsg::UpdateBurnDestination { burn_destination } => {
+            update_burn_destination(deps, info, burn_destination)
+        }
+        ExecuteMsg::BurnFrom { denom, amount } => burn_from(deps, _env, info, denom, amount),
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps<TokenFactoryQuery>, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps<TokenFactoryQuery>, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::GetConfig {} => to_binary(&CONFIG.load(deps.storage)?),
         QueryMsg::GetStats {} => to_binary(&STATS.load(deps.storage)?),
         QueryMsg::GetBurnedSnapshot {} => to_binary(&BURNED_REMINTED_SNAPSHOT.load(deps.storage)?),
         QueryMsg::GetToBurn {} => to_binary(&TO_BURN.load(deps.storage)?),
+        QueryMsg::GetSwapLimits {} => to_binary(
+            &SWAP_LIMITS
+                .may_load(deps.storage)?
+                .unwrap_or_else(SwapLimits::unlimited),
+        ),
+        QueryMsg::GetSwapAllowance { address } => {
+            let address = deps.api.addr_validate(&address)?;
+            let swap_limits = SWAP_LIMITS
+                .may_load(deps.storage)?
+                .unwrap_or_else(SwapLimits::unlimited);
+            let swapped = SWAPPED_BY_ADDRESS
+                .may_load(deps.storage, &address)?
+                .unwrap_or_else(AddressSwapped::zero);
+            to_binary(&swap_limits.allowance(&swapped, &env.block))
+        }
+        QueryMsg::GetCircuitBreaker {} => {
+            let circuit_breaker = CIRCUIT_BREAKER.may_load(deps.storage)?;
+            let volume = SWAP_VOLUME
+                .may_load(deps.storage)?
+                .unwrap_or_else(SwapVolume::zero);
+            let window_amount = circuit_breaker
+                .as_ref()
+                .map(|circuit_breaker| circuit_breaker.window_amount(&volume, &env.block))
+                .unwrap_or_default();
+            to_binary(&CircuitBreakerStatus {
+                circuit_breaker,
+                volume,
+                window_amount,
+            })
+        }
+        QueryMsg::GetRoleMembers {
+            role,
+            start_after,
+            limit,
+        } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let start_after = start_after
+                .map(|address| deps.api.addr_validate(&address))
+                .transpose()?;
+            let members = ROLES
+                .prefix(role.as_str())
+                .keys(
+                    deps.storage,
+                    start_after.as_ref().map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit)
+                .map(|address| address.map(String::from))
+                .collect::<StdResult<Vec<String>>>()?;
+            to_binary(&RoleMembers { role, members })
+        }
+        QueryMsg::GetRoles { address } => {
+            let config = CONFIG.load(deps.storage)?;
+            let address = deps.api.addr_validate(&address)?;
+            let roles: Vec<Role> = Role::all()
+                .into_iter()
+                .filter(|role| has_role(deps.storage, &config, &address, *role))
+                .collect();
+            to_binary(&roles)
+        }
+        QueryMsg::GetPauseStatus {} => {
+            let statuses = Operation::all()
+                .into_iter()
+                .map(|operation| {
+                    Ok(OperationStatus {
+                        operation,
+                        paused: PAUSED.may_load(deps.storage, operation.as_str())?,
+                    })
+                })
+                .collect::<StdResult<Vec<OperationStatus>>>()?;
+            to_binary(&statuses)
+        }
+        QueryMsg::GetDenomMetadata {} => to_binary(&DENOM_METADATA.may_load(deps.storage)?),
+        QueryMsg::GetProduct { denom } => {
+            let config = CONFIG.load(deps.storage)?;
+            load_product(deps.storage, &config, denom)
+                .map_err(|err| StdError::generic_err(err.to_string()))
+                .and_then(|product| to_binary(&product))
+        }
+        QueryMsg::ListProducts { start_after, limit } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let products = PRODUCTS
+                .range(
+                    deps.storage,
+                    start_after.as_deref().map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit)
+                .map(|item| item.map(|(_, product)| product))
+                .collect::<StdResult<Vec<Product>>>()?;
+            to_binary(&products)
+        }
+        QueryMsg::GetDenomAdmin { denom } => {
+            let config = CONFIG.load(deps.storage)?;
+            let product = load_product(deps.storage, &config, denom)
+                .map_err(|err| StdError::generic_err(err.to_string()))?;
+            let admin: AdminResponse =
+                deps.querier
+                    .query(&QueryRequest::Custom(TokenFactoryQuery::Admin {
+                        denom: product.denom,
+                    }))?;
+            to_binary(&admin)
+        }
+        QueryMsg::GetSupplyInfo { denom } => {
+            let config = CONFIG.load(deps.storage)?;
+            let product = load_product(deps.storage, &config, denom)
+                .map_err(|err| StdError::generic_err(err.to_string()))?;
+            to_binary(&query_supply_info(&deps.querier, &product)?)
+        }
+        QueryMsg::GetClaim { address } => {
+            let address = deps.api.addr_validate(&address)?;
+            to_binary(&CLAIMS.may_load(deps.storage, &address)?.unwrap_or_default())
+        }
+        QueryMsg::GetBurnDestination {} => to_binary(
+            &BURN_DESTINATION
+                .may_load(deps.storage)?
+                .unwrap_or(BurnDestination::Hold),
+        ),
+        QueryMsg::GetBurnCrank {} => to_binary(
+            &BURN_CRANK
+                .may_load(deps.storage)?
+                .unwrap_or_else(BurnCrank::disabled),
+        ),
+        QueryMsg::GetReferral {} => to_binary(
+            &REFERRAL
+                .may_load(deps.storage)?
+                .unwrap_or_else(Referral::disabled),
+        ),
+        QueryMsg::GetReferrer { address } => {
+            let address = deps.api.addr_validate(&address)?;
+            to_binary(
+                &REFERRERS
+                    .may_load(deps.storage, &address)?
+                    .unwrap_or_else(|| ReferrerStats::zero(address)),
+            )
+        }
+        QueryMsg::ListTopReferrers { limit } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let referrers = REFERRERS_BY_VOLUME
+                .keys(deps.storage, None, None, Order::Descending)
+                .take(limit)
+                .map(|key| key.and_then(|(_, address)| REFERRERS.load(deps.storage, &address)))
+                .collect::<StdResult<Vec<ReferrerStats>>>()?;
+            to_binary(&referrers)
+        }
+        QueryMsg::GetVesting {} => to_binary(
+            &VESTING
+                .may_load(deps.storage)?
+                .unwrap_or_else(Vesting::disabled),
+        ),
+        QueryMsg::ListVestingPositions {
+            address,
+            start_after,
+            limit,
+        } => {
+            let address = deps.api.addr_validate(&address)?;
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let positions = VESTING_POSITIONS
+                .prefix(&address)
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit)
+                .map(|item| item.map(|(_, position)| position))
+                .collect::<StdResult<Vec<VestingPosition>>>()?;
+            to_binary(&positions)
+        }
+        QueryMsg::GetClaimableVested { address } => {
+            let address = deps.api.addr_validate(&address)?;
+            let mut claimable: Vec<Coin> = vec![];
+            for item in
+                VESTING_POSITIONS
+                    .prefix(&address)
+                    .range(deps.storage, None, None, Order::Ascending)
+            {
+                let (_, position) = item?;
+                let amount = position.claimable(env.block.time);
+                match claimable
+                    .iter_mut()
+                    .find(|coin| coin.denom == position.denom)
+                {
+                    Some(coin) => coin.amount += amount,
+                    None => claimable.push(Coin {
+                        denom: position.denom,
+                        amount,
+                    }),
+                }
+            }
+            to_binary(&claimable)
+        }
+        QueryMsg::GetLockup {} => to_binary(
+            &LOCKUP
+                .may_load(deps.storage)?
+                .unwrap_or_else(Lockup::disabled),
+        ),
+        QueryMsg::ListLocks {
+            address,
+            start_after,
+            limit,
+        } => {
+            let address = deps.api.addr_validate(&address)?;
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let locks = LOCKS
+                .prefix(&address)
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit)
+                .map(|item| item.map(|(_, lock)| lock))
+                .collect::<StdResult<Vec<Lock>>>()?;
+            to_binary(&locks)
+        }
+        QueryMsg::SimulateSwap {
+            amount,
+            product,
+            lock_duration,
+        } => {
+            let config = CONFIG.load(deps.storage)?;
+            let simulation = load_product(deps.storage, &config, product)
+                .and_then(|product| simulate_swap(deps, &env, &product, amount, lock_duration))
+                .map_err(|err| StdError::generic_err(err.to_string()))?;
+            to_binary(&simulation)
+        }
+        QueryMsg::GetCw20Allowlist {} => {
+            let tokens = CW20_ALLOWLIST
+                .keys(deps.storage, None, None, Order::Ascending)
+                .map(|token| token.map(String::from))
+                .collect::<StdResult<Vec<String>>>()?;
+            to_binary(&tokens)
+        }
+        QueryMsg::GetCw20Stats { token } => {
+            let token = deps.api.addr_validate(&token)?;
+            to_binary(
+                &CW20_STATS
+                    .may_load(deps.storage, &token)?
+                    .unwrap_or_else(Statistics::zero),
+            )
+        }
+        QueryMsg::GetIntermediateSender {
+            channel,
+            original_sender,
+        } => to_binary(&derive_intermediate_sender(
+            &channel,
+            &original_sender,
+            BECH32_PREFIX,
+        )?),
+        QueryMsg::GetRedemption {} => {
+            let config = CONFIG.load(deps.storage)?;
+            to_binary(&query_redemption_status(deps, &config)?)
+        }
+        QueryMsg::GetBurnHistory { start_after, limit } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let records = BURN_HISTORY
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit)
+                .map(|item| item.map(|(_, record)| record))
+                .collect::<StdResult<Vec<BurnRecord>>>()?;
+            to_binary(&records)
+        }
+        QueryMsg::GetSnapshotDebt {} => {
+            let snapshot = BURNED_REMINTED_SNAPSHOT.may_load(deps.storage)?;
+            let settlement = SNAPSHOT_SETTLEMENT
+                .may_load(deps.storage)?
+                .unwrap_or_else(SnapshotSettlement::zero);
+            let (denom, amount) = match snapshot {
+                Some(snapshot) => (snapshot.denom, snapshot.amount),
+                None => (CONFIG.load(deps.storage)?.accepted_denom, Uint128::zero()),
+            };
+            to_binary(&SnapshotDebt {
+                enabled: settlement.enabled,
+                denom,
+                snapshot: amount,
+                settled: settlement.settled,
+                remaining: amount.saturating_sub(settlement.settled),
+                last_settled_at: settlement.last_settled_at,
+            })
+        }
+        QueryMsg::GetRecoverable {} => {
+            let config = CONFIG.load(deps.storage)?;
+            let recoverable = deps
+                .querier
+                .query_all_balances(&env.contract.address)?
+                .into_iter()
+                .map(|balance| {
+                    Ok(Coin {
+                        amount: recoverable_amount(deps.storage, &config, &balance)?,
+                        denom: balance.denom,
+                    })
+                })
+                .filter(|coin| !matches!(coin, Ok(coin) if coin.amount.is_zero()))
+                .collect::<StdResult<Vec<Coin>>>()?;
+            to_binary(&recoverable)
+        }
+        QueryMsg::CheckInvariants {} => to_binary(&check_invariants(deps, &env)?),
+        QueryMsg::GetLifecycle {} => {
+            let finalized_at = FINALIZED_AT.may_load(deps.storage)?;
+            let phase = if finalized_at.is_some() {
+                Phase::Finalized
+            } else {
+                let config = CONFIG.load(deps.storage)?;
+                let product = load_product(deps.storage, &config, None)
+                    .map_err(|err| StdError::generic_err(err.to_string()))?;
+                if is_minting_finished(&STATS.load(deps.storage)?, &product) {
+                    Phase::CapReached
+                } else if !config.enabled
+                    || is_paused(deps.storage, Operation::Swap)
+                    || matches!(
+                        SWAP_VOLUME.may_load(deps.storage)?,
+                        Some(SwapVolume {
+                            tripped_at: Some(_),
+                            ..
+                        })
+                    )
+                {
+                    Phase::Paused
+                } else {
+                    Phase::Active
+                }
+            };
+            to_binary(&Lifecycle {
+                phase,
+                finalized_at,
+            })
+        }
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(
+pub fn reply(
     deps: DepsMut<TokenFactoryQuery>,
-    env: Env,
-    msg: MigrateMsg,
+    _env: Env,
+    msg: Reply,
 ) -> Result<Response<TokenFactoryMsg>, ContractError> {
-    if msg.states_update {
-        let config = CONFIG.load(deps.storage)?;
-        let stats = STATS.load(deps.storage)?;
-
-        // Starts from 0 for the new burn module
-        TO_BURN.save(
-            deps.storage,
-            &Coin {
-                denom: config.accepted_denom.clone(),
-                amount: Uint128::zero(),
-            },
-        )?;
-
-        // This was burned but will be reminted - need to check if Juno can burn it when the new module
-        // happens
-        BURNED_REMINTED_SNAPSHOT.save(
-            deps.storage,
-            &BurnedSnapshot {
-                denom: config.accepted_denom,
-                amount: stats.burned,
-                snapshot_time: env.block.time,
-            },
-        )?;
+    match msg.id {
+        CREATE_DENOM_REPLY_ID => create_denom_reply(deps, msg.result),
+        IBC_FORWARD_REPLY_ID => ibc_forward_reply(deps, msg.result),
+        id if id >= PAYOUT_REPLY_ID_START => payout_reply(deps, id, msg.result),
+        id => Err(ContractError::UnknownReplyId { id }),
     }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(
+    deps: DepsMut<TokenFactoryQuery>,
+    _env: Env,
+    msg: SudoMsg,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    match msg {
+        SudoMsg::IbcLifecycleComplete(lifecycle) => ibc_lifecycle_complete(deps, lifecycle),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(
+    mut deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    _msg: MigrateMsg,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    ensure!(
+        stored.contract == CONTRACT_NAME,
+        ContractError::WrongContract {
+            name: stored.contract
+        }
+    );
+
+    let from = parse_version(&stored.version)?;
+    let to = parse_version(CONTRACT_VERSION)?;
+    ensure!(
+        from <= to,
+        ContractError::CannotDowngrade {
+            from: stored.version,
+            to: CONTRACT_VERSION.to_string(),
+        }
+    );
+
+    let steps = run_migrations(deps.branch(), &env, &from, &to)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-    Ok(Response::default())
+    Ok(Response::new().add_event(
+        Event::new("migrated")
+            .add_attribute("from", stored.version)
+            .add_attribute("to", CONTRACT_VERSION)
+            .add_attribute("steps", steps.join(",")),
+    ))
 }
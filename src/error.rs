@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -18,15 +18,181 @@ pub enum ContractError {
     #[error("InvalidInitMsg")]
     InvalidInitMsg {},
 
-    #[error("InvalidFundsReceived")]
-    InvalidFundsReceived {},
+    #[error("WrongDenom - expected: {expected}, received: {received}")]
+    WrongDenom { expected: String, received: String },
+
+    #[error("MultipleCoins - expected: {expected}, received: {received}")]
+    MultipleCoins { expected: String, received: String },
 
     #[error("InvalidAmountsDistribution")]
     InvalidAmountsDistribution {},
 
-    #[error("MaxSupplyReached")]
-    MaxSupplyReceivedReached {},
+    #[error("MaxSupplyReceivedReached - amount: {amount}, remaining: {remaining}")]
+    MaxSupplyReceivedReached { amount: Uint128, remaining: Uint128 },
+
+    #[error("MaxSupplyReached - denom: {denom}, amount: {amount}, remaining: {remaining}")]
+    MaxSupplyReached {
+        denom: String,
+        amount: Uint128,
+        remaining: Uint128,
+    },
+
+    #[error("InvalidSwapLimits")]
+    InvalidSwapLimits {},
+
+    #[error("SwapLimitPerTxExceeded - remaining: {remaining}")]
+    SwapLimitPerTxExceeded { remaining: Uint128 },
+
+    #[error("SwapLimitPerAddressExceeded - remaining: {remaining}")]
+    SwapLimitPerAddressExceeded { remaining: Uint128 },
+
+    #[error("SwapLimitPerWindowExceeded - remaining: {remaining}")]
+    SwapLimitPerWindowExceeded { remaining: Uint128 },
+
+    #[error("InvalidCircuitBreaker")]
+    InvalidCircuitBreaker {},
+
+    #[error("CircuitBreakerTripped")]
+    CircuitBreakerTripped {},
+
+    #[error("RoleNotGranted")]
+    RoleNotGranted {},
+
+    #[error("OperationPaused - {operation}")]
+    OperationPaused { operation: String },
+
+    #[error("InvalidDenomMetadata")]
+    InvalidDenomMetadata {},
+
+    #[error("InvalidSubdenom")]
+    InvalidSubdenom {},
+
+    #[error("InvalidProduct")]
+    InvalidProduct {},
+
+    #[error("ProductAlreadyExists")]
+    ProductAlreadyExists {},
+
+    #[error("UnknownProduct")]
+    UnknownProduct {},
+
+    #[error("ZeroAmount - {field}")]
+    ZeroAmount { field: String },
+
+    #[error("MintingNotFinished")]
+    MintingNotFinished {},
+
+    #[error("DenomAdminTransferred")]
+    DenomAdminTransferred {},
+
+    #[error("InvalidDenomAdmin")]
+    InvalidDenomAdmin {},
+
+    #[error("SwapFinalized")]
+    SwapFinalized {},
+
+    #[error("NothingToClaim")]
+    NothingToClaim {},
+
+    #[error("UnknownReplyId - {id}")]
+    UnknownReplyId { id: u64 },
+
+    #[error("InvalidBurnCrank")]
+    InvalidBurnCrank {},
+
+    #[error("BurnCrankNotReady")]
+    BurnCrankNotReady {},
+
+    #[error("NoSnapshotDebt")]
+    NoSnapshotDebt {},
+
+    #[error("SnapshotDebtExceeded - remaining: {remaining}")]
+    SnapshotDebtExceeded { remaining: Uint128 },
+
+    #[error("WrongContract - {name}")]
+    WrongContract { name: String },
+
+    #[error("InvalidVersion - {version}")]
+    InvalidVersion { version: String },
+
+    #[error("CannotDowngrade - from {from} to {to}")]
+    CannotDowngrade { from: String, to: String },
+
+    #[error("InvalidStoredAddress - {field}: {address}")]
+    InvalidStoredAddress { field: String, address: String },
+
+    #[error("NotRecoverable - recoverable: {recoverable}")]
+    NotRecoverable { recoverable: Uint128 },
+
+    #[error("InvariantViolated - {invariants}")]
+    InvariantViolated { invariants: String },
+
+    #[error("NonPayable")]
+    NonPayable {},
+
+    #[error("InvalidReferral")]
+    InvalidReferral {},
+
+    #[error("ReferralDisabled")]
+    ReferralDisabled {},
+
+    #[error("SelfReferral")]
+    SelfReferral {},
+
+    #[error("InvalidVesting")]
+    InvalidVesting {},
+
+    #[error("VestingOutstanding - {amount}")]
+    VestingOutstanding { amount: Uint128 },
+
+    #[error("InvalidLockup")]
+    InvalidLockup {},
+
+    #[error("NoLockBonus - {lock_duration}")]
+    NoLockBonus { lock_duration: u64 },
+
+    #[error("LockShorterThanVesting - lock_duration: {lock_duration}, vesting: {vesting}")]
+    LockShorterThanVesting { lock_duration: u64, vesting: u64 },
+
+    #[error("LockTooLong - lock_duration: {lock_duration}, longest: {longest}")]
+    LockTooLong { lock_duration: u64, longest: u64 },
+
+    #[error("LocksOutstanding - {amount}")]
+    LocksOutstanding { amount: Uint128 },
+
+    #[error("NothingToWithdraw")]
+    NothingToWithdraw {},
+
+    #[error("InvalidRedemption")]
+    InvalidRedemption {},
+
+    #[error("RedemptionClosed")]
+    RedemptionClosed {},
+
+    #[error("InsufficientReserve - reserve: {reserve}, required: {required}")]
+    InsufficientReserve { reserve: Uint128, required: Uint128 },
+
+    #[error("Cw20NotAllowed - {token}")]
+    Cw20NotAllowed { token: String },
+
+    #[error("InvalidIbcForward")]
+    InvalidIbcForward {},
+
+    #[error("IbcForwardNotMinted")]
+    IbcForwardNotMinted {},
+
+    #[error("RecipientRequired")]
+    RecipientRequired {},
+
+    #[error("UnknownIbcPacket - channel: {channel}, sequence: {sequence}")]
+    UnknownIbcPacket { channel: String, sequence: u64 },
+
+    #[error("InvalidTransferResponse")]
+    InvalidTransferResponse {},
+
+    #[error("BurnExceedsBalance - balance: {balance}, amount: {amount}")]
+    BurnExceedsBalance { balance: Uint128, amount: Uint128 },
 
-    #[error("MaxSupplyReached")]
-    MaxSupplyReached {},
+    #[error("SnapshotSettlementDisabled")]
+    SnapshotSettlementDisabled {},
 }
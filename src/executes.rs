@@ -1,94 +1,1707 @@
-use cosmwasm_std::{ensure, Coin, Decimal, DepsMut, Env, MessageInfo, Response};
+use cosmwasm_std::{
+    ensure, from_binary, Addr, BankMsg, Coin, Decimal, DepsMut, Empty, Env, Event, MessageInfo,
+    Order, Response, StdError, StdResult, Storage, SubMsg, SubMsgResponse, SubMsgResult, Uint128,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw_utils::parse_execute_response_data;
 use token_bindings::{TokenFactoryMsg, TokenFactoryQuery};
 
-use crate::constants::{BALANCE_MAX_SUPPLY, JUNO_MAX_SUPPLY};
+use crate::constants::{CREATE_DENOM_REPLY_ID, JUNO_MAX_SUPPLY, RENOUNCED_ADMIN};
 use crate::error::ContractError;
-use crate::helpers::{compute_amounts_to_distribute, validate_coin_received};
-use crate::state::{CONFIG, STATS, TO_BURN};
+use crate::helpers::{
+    checked_plus_seconds, compute_amounts_to_distribute, compute_amounts_to_sweep,
+    cw20_execute_msg, ensure_role, has_role, ibc_forward_submsg, is_minting_finished, is_paused,
+    load_product, lock_bonus, payout_submsg, query_supply_info, recoverable_amount, route_burn,
+    transfer_sequence, validate_coin_received,
+};
+use crate::msg::{IbcLifecycleComplete, ReceiveMsg};
+use crate::state::{
+    AddressSwapped, BurnCrank, BurnDestination, BurnRecord, CircuitBreaker, Config, DenomMetadata,
+    IbcForward, Lock, Lockup, Operation, PauseInfo, PendingIbcForward, Product, Redemption,
+    Referral, ReferralSource, ReferrerStats, Role, SnapshotSettlement, Statistics, SwapLimits,
+    SwapVolume, Vesting, VestingPosition, BURNED_REMINTED_SNAPSHOT, BURN_CRANK, BURN_DESTINATION,
+    BURN_HISTORY, CIRCUIT_BREAKER, CLAIMS, CLAIMS_TOTAL, CONFIG, CW20_ALLOWLIST, CW20_STATS,
+    DENOM_METADATA, FINALIZED_AT, IBC_FORWARDS, LOCKS, LOCKUP, NEXT_LOCK_ID, NEXT_VESTING_ID,
+    PAUSED, PENDING_DENOM, PENDING_IBC_FORWARD, PENDING_PAYOUTS, PRODUCTS, REDEEMED_HELD,
+    REDEMPTION, REFERRAL, REFERRERS, REFERRERS_BY_VOLUME, RESERVE, ROLES, SNAPSHOT_SETTLEMENT,
+    STATS, SWAPPED_BY_ADDRESS, SWAP_LIMITS, SWAP_VOLUME, TO_BURN, VESTING, VESTING_POSITIONS,
+};
 
+#[allow(clippy::too_many_arguments)]
 pub fn swap(
     deps: DepsMut<TokenFactoryQuery>,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
+    product: Option<String>,
+    referrer: Option<String>,
+    lock_duration: Option<u64>,
+    recipient: Option<String>,
+    forward_ibc: Option<IbcForward>,
 ) -> Result<Response<TokenFactoryMsg>, ContractError> {
     let config = CONFIG.load(deps.storage)?;
-    let mut stats = STATS.load(deps.storage)?;
-
     validate_coin_received(&config.accepted_denom, &info)?;
+    do_swap(
+        deps,
+        env,
+        SwapInput::Native,
+        info.funds[0].amount,
+        info.sender,
+        SwapOptions {
+            product,
+            referrer,
+            lock_duration,
+            recipient,
+            forward_ibc,
+        },
+    )
+}
+
+// What the swap is paid with
+enum SwapInput {
+    // The accepted denom, sent with `Swap`
+    Native,
+    // An allowlisted CW20, sent with `Receive`
+    Cw20(Addr),
+}
 
+#[derive(Default)]
+struct SwapOptions {
+    product: Option<String>,
+    referrer: Option<String>,
+    lock_duration: Option<u64>,
+    recipient: Option<String>,
+    forward_ibc: Option<IbcForward>,
+}
+
+// Shared by the native and CW20 swaps: the guards, limits, product minted and shares distributed
+fn do_swap(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    input: SwapInput,
+    amount: Uint128,
+    sender: Addr,
+    options: SwapOptions,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let SwapOptions {
+        product,
+        referrer,
+        lock_duration,
+        recipient,
+        forward_ibc,
+    } = options;
+    let config = CONFIG.load(deps.storage)?;
     ensure!(
-        stats.received + info.funds[0].amount <= JUNO_MAX_SUPPLY,
-        ContractError::MaxSupplyReceivedReached {}
+        FINALIZED_AT.may_load(deps.storage)?.is_none(),
+        ContractError::SwapFinalized {}
     );
+    #[cfg(feature = "invariant-checks")]
+    {
+        let violations = crate::helpers::check_invariants(deps.as_ref(), &env)?;
+        ensure!(
+            violations.is_empty(),
+            ContractError::InvariantViolated {
+                invariants: violations
+                    .iter()
+                    .map(|violation| violation.invariant.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(",")
+            }
+        );
+    }
+    let mut stats = STATS.load(deps.storage)?;
+    let mut product = load_product(deps.storage, &config, product)?;
+    ensure!(
+        product.admin_transferred_to.is_none(),
+        ContractError::DenomAdminTransferred {}
+    );
+
+    // Forwarded swaps come from the ibc-hooks intermediate sender, refunds go to the recipient
+    ensure!(
+        forward_ibc.is_none() || recipient.is_some(),
+        ContractError::RecipientRequired {}
+    );
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => sender.clone(),
+    };
+    let referral = match referrer {
+        Some(referrer) => {
+            let referrer = deps.api.addr_validate(&referrer)?;
+            ensure!(
+                referrer != sender && referrer != recipient,
+                ContractError::SelfReferral {}
+            );
+            let referral = REFERRAL
+                .may_load(deps.storage)?
+                .unwrap_or_else(Referral::disabled);
+            ensure!(referral.enabled, ContractError::ReferralDisabled {});
+            Some((referrer, referral))
+        }
+        None => None,
+    };
+    let forward_ibc = forward_ibc.map(IbcForward::validate).transpose()?;
+    let scheduled_bonus = lock_bonus(deps.storage, lock_duration)?;
+    // Limits and vesting follow the recipient, ibc-hooks swaps being sent by an intermediate address
+    let mut swapped = SWAPPED_BY_ADDRESS
+        .may_load(deps.storage, &recipient)?
+        .unwrap_or_else(AddressSwapped::zero);
+    // The tier follows the total swapped by the address, splitting the swaps doesn't skip vesting
+    let vesting_tier = VESTING
+        .may_load(deps.storage)?
+        .and_then(|vesting| vesting.tier(swapped.total + amount).cloned());
+    // Locked tokens aren't vested on top, as long as the lock outlasts the vesting
+    if let (Some(lock_duration), Some(tier)) = (lock_duration, &vesting_tier) {
+        let vesting = tier.cliff.saturating_add(tier.duration);
+        ensure!(
+            lock_duration >= vesting,
+            ContractError::LockShorterThanVesting {
+                lock_duration,
+                vesting
+            }
+        );
+    }
+    let vesting_tier = vesting_tier.filter(|_| lock_duration.is_none());
+    // Only the product minted with the swap can be forwarded
+    ensure!(
+        forward_ibc.is_none() || (lock_duration.is_none() && vesting_tier.is_none()),
+        ContractError::IbcForwardNotMinted {}
+    );
+
+    if let SwapInput::Native = input {
+        ensure!(
+            stats.received + amount <= JUNO_MAX_SUPPLY,
+            ContractError::MaxSupplyReceivedReached {
+                amount,
+                remaining: JUNO_MAX_SUPPLY.saturating_sub(stats.received),
+            }
+        );
+    }
 
     let mut response = Response::new();
 
-    let amounts_to_distribute = compute_amounts_to_distribute(&config, info.funds[0].amount)?;
+    // Global volume circuit breaker - the swap crossing it is refunded and swapping stays paused
+    let mut volume = SWAP_VOLUME
+        .may_load(deps.storage)?
+        .unwrap_or_else(SwapVolume::zero);
+    ensure!(
+        volume.tripped_at.is_none(),
+        ContractError::CircuitBreakerTripped {}
+    );
+    if let Some(circuit_breaker) = CIRCUIT_BREAKER.may_load(deps.storage)? {
+        let tripped = circuit_breaker.record(&mut volume, amount, &env.block);
+        SWAP_VOLUME.save(deps.storage, &volume)?;
+        if tripped {
+            // To the recipient, the sender of ibc-hooks swaps being an intermediate address
+            let refund_msg = match &input {
+                SwapInput::Native => BankMsg::Send {
+                    to_address: recipient.to_string(),
+                    amount: vec![Coin {
+                        denom: config.accepted_denom.clone(),
+                        amount,
+                    }],
+                }
+                .into(),
+                SwapInput::Cw20(token) => cw20_execute_msg(
+                    token,
+                    &Cw20ExecuteMsg::Transfer {
+                        recipient: recipient.to_string(),
+                        amount,
+                    },
+                )?,
+            };
+            return Ok(Response::new().add_message(refund_msg).add_event(
+                Event::new("circuit_breaker_tripped")
+                    .add_attribute("amount", amount)
+                    .add_attribute(
+                        "window_amount",
+                        circuit_breaker.window_amount(&volume, &env.block),
+                    )
+                    .add_attribute("max_volume", circuit_breaker.max_volume),
+            ));
+        }
+    }
+
+    // Per transaction and per address limits on the amount received
+    let swap_limits = SWAP_LIMITS
+        .may_load(deps.storage)?
+        .unwrap_or_else(SwapLimits::unlimited);
+    swap_limits.check_and_record(&mut swapped, amount, &env.block)?;
+    SWAPPED_BY_ADDRESS.save(deps.storage, &recipient, &swapped)?;
+
+    let mut amounts_to_distribute = compute_amounts_to_distribute(&config, amount)?;
 
-    // Send Balance to the sender
-    let token_amount_to_send =
-        info.funds[0].amount * Decimal::from_ratio(BALANCE_MAX_SUPPLY, JUNO_MAX_SUPPLY);
+    // Send the product to the recipient, with the lock bonus taken from the room under the cap
+    let token_amount_swapped = amount * product.rate;
+    let bonus = scheduled_bonus
+        .map(|bonus| Decimal::permille(bonus.bonus_permille_u64) * token_amount_swapped)
+        .unwrap_or_default();
+    let token_amount_to_send = token_amount_swapped + bonus;
+
+    // Referral share, out of the dev fees or minted on top of the product
+    let mut referral_minted = Uint128::zero();
+    if let Some((_, referral)) = &referral {
+        let share = Decimal::permille(referral.share_permille_u64);
+        match referral.source {
+            ReferralSource::DevFees => {
+                amounts_to_distribute.referral = share * amounts_to_distribute.dev;
+                amounts_to_distribute.dev -= amounts_to_distribute.referral;
+            }
+            ReferralSource::Mint => referral_minted = share * token_amount_swapped,
+        }
+    }
+    let supply_info = query_supply_info(&deps.querier, &product)?;
+    if supply_info.is_mismatch() {
+        response = response.add_event(
+            Event::new("supply_mismatch")
+                .add_attribute("denom", supply_info.denom)
+                .add_attribute("recorded", supply_info.recorded)
+                .add_attribute("bank_supply", supply_info.bank_supply),
+        );
+    }
     ensure!(
-        stats.distributed + token_amount_to_send <= BALANCE_MAX_SUPPLY,
-        ContractError::MaxSupplyReached {}
+        token_amount_to_send + referral_minted <= supply_info.remaining,
+        ContractError::MaxSupplyReached {
+            denom: product.denom.clone(),
+            amount: token_amount_to_send + referral_minted,
+            remaining: supply_info.remaining,
+        }
     );
+    product.minted += token_amount_to_send + referral_minted;
+    if vesting_tier.is_some() {
+        product.vesting += token_amount_to_send;
+    }
+    if lock_duration.is_some() {
+        product.locked += token_amount_to_send;
+    }
+    PRODUCTS.save(deps.storage, &product.denom, &product)?;
 
-    // Update statistics
-    let stats = stats.add(
-        &amounts_to_distribute,
-        token_amount_to_send,
-        info.funds[0].amount,
+    // Juno sent by the swap, left out of the finalization sweep
+    let mut amount_sent = Uint128::zero();
+    match &input {
+        SwapInput::Native => {
+            // Update statistics
+            stats.add(
+                &amounts_to_distribute,
+                token_amount_to_send + referral_minted,
+                amount,
+            );
+
+            // Burn: kept in the contract to be burned with `Burn` msg later, or sent to the burn destination
+            // TODO: To be changed when the new burn module is live on Juno
+            let burn_msg = route_burn(
+                deps.storage,
+                &mut stats,
+                Coin {
+                    denom: config.accepted_denom.clone(),
+                    amount: amounts_to_distribute.burned,
+                },
+            )?;
+            if let Some(burn_msg) = burn_msg {
+                response = response.add_message(burn_msg);
+                amount_sent += amounts_to_distribute.burned;
+            }
+
+            // Dev funds
+            response = response.add_submessages(payout_submsg(
+                deps.storage,
+                &config.balance_development_fund_addr,
+                Coin {
+                    denom: config.accepted_denom.clone(),
+                    amount: amounts_to_distribute.balance_dev_fund,
+                },
+            )?);
+
+            // Vesting
+            response = response.add_submessages(payout_submsg(
+                deps.storage,
+                &config.juno_development_fund_addr,
+                Coin {
+                    denom: config.accepted_denom.clone(),
+                    amount: amounts_to_distribute.juno_dev_fund,
+                },
+            )?);
+
+            // Dev
+            response = response.add_submessages(payout_submsg(
+                deps.storage,
+                &config.dev_addr,
+                Coin {
+                    denom: config.accepted_denom.clone(),
+                    amount: amounts_to_distribute.dev,
+                },
+            )?);
+            amount_sent += amounts_to_distribute.balance_dev_fund
+                + amounts_to_distribute.juno_dev_fund
+                + amounts_to_distribute.dev
+                + amounts_to_distribute.referral;
+        }
+        SwapInput::Cw20(token) => {
+            // The products minted are counted for every swap
+            stats.distributed += token_amount_to_send + referral_minted;
+            let mut cw20_stats = CW20_STATS
+                .may_load(deps.storage, token)?
+                .unwrap_or_else(Statistics::zero);
+            cw20_stats.add(&amounts_to_distribute, token_amount_to_send, amount);
+            CW20_STATS.save(deps.storage, token, &cw20_stats)?;
+
+            // The shares are transferred and the burned share is burned
+            response = response
+                .add_attribute("action", "swap_cw20")
+                .add_attribute("token", token.clone())
+                .add_attribute("amount", amount);
+            if !amounts_to_distribute.burned.is_zero() {
+                response = response.add_message(cw20_execute_msg(
+                    token,
+                    &Cw20ExecuteMsg::Burn {
+                        amount: amounts_to_distribute.burned,
+                    },
+                )?);
+            }
+            for (address, amount) in [
+                (
+                    &config.balance_development_fund_addr,
+                    amounts_to_distribute.balance_dev_fund,
+                ),
+                (
+                    &config.juno_development_fund_addr,
+                    amounts_to_distribute.juno_dev_fund,
+                ),
+                (&config.dev_addr, amounts_to_distribute.dev),
+            ] {
+                if !amount.is_zero() {
+                    response = response.add_message(cw20_execute_msg(
+                        token,
+                        &Cw20ExecuteMsg::Transfer {
+                            recipient: address.to_string(),
+                            amount,
+                        },
+                    )?);
+                }
+            }
+        }
+    }
+    STATS.save(deps.storage, &stats)?;
+
+    // Minted when withdrawn with a lock, or when claimed with a vesting tier
+    if let Some(lock_duration) = lock_duration {
+        let id = NEXT_LOCK_ID.may_load(deps.storage)?.unwrap_or_default();
+        NEXT_LOCK_ID.save(deps.storage, &(id + 1))?;
+        let unlocks_at = checked_plus_seconds(env.block.time, lock_duration)?;
+        LOCKS.save(
+            deps.storage,
+            (&recipient, id),
+            &Lock {
+                id,
+                owner: recipient.clone(),
+                denom: product.denom.clone(),
+                amount: token_amount_to_send,
+                bonus,
+                unlocks_at,
+            },
+        )?;
+        response = response.add_event(
+            Event::new("lock")
+                .add_attribute("id", id.to_string())
+                .add_attribute("owner", recipient.clone())
+                .add_attribute("amount", token_amount_to_send)
+                .add_attribute("bonus", bonus)
+                .add_attribute("unlocks_at", unlocks_at.to_string()),
+        );
+    } else if let Some(tier) = vesting_tier {
+        let id = NEXT_VESTING_ID.may_load(deps.storage)?.unwrap_or_default();
+        NEXT_VESTING_ID.save(deps.storage, &(id + 1))?;
+        VESTING_POSITIONS.save(
+            deps.storage,
+            (&recipient, id),
+            &VestingPosition {
+                id,
+                owner: recipient.clone(),
+                denom: product.denom.clone(),
+                amount: token_amount_to_send,
+                claimed: Uint128::zero(),
+                start: env.block.time,
+                cliff: tier.cliff,
+                duration: tier.duration,
+            },
+        )?;
+        response = response.add_event(
+            Event::new("vesting_position")
+                .add_attribute("id", id.to_string())
+                .add_attribute("owner", recipient.clone())
+                .add_attribute("amount", token_amount_to_send),
+        );
+    } else if let Some(forward_ibc) = forward_ibc {
+        // Minted to the contract then sent over IBC
+        response = response
+            .add_message(TokenFactoryMsg::mint_contract_tokens(
+                product.denom.clone(),
+                token_amount_to_send,
+                env.contract.address.to_string(),
+            ))
+            .add_submessage(ibc_forward_submsg(
+                deps.storage,
+                &env,
+                &recipient,
+                forward_ibc,
+                Coin {
+                    denom: product.denom.clone(),
+                    amount: token_amount_to_send,
+                },
+            )?);
+    } else {
+        let mint_tokens_msg = TokenFactoryMsg::mint_contract_tokens(
+            product.denom.clone(),
+            token_amount_to_send,
+            recipient.to_string(),
+        );
+
+        response = response.add_message(mint_tokens_msg);
+    }
+
+    // Referrer
+    if let Some((referrer, _)) = referral {
+        let mut referrer_stats = REFERRERS
+            .may_load(deps.storage, &referrer)?
+            .unwrap_or_else(|| ReferrerStats::zero(referrer.clone()));
+        REFERRERS_BY_VOLUME.remove(deps.storage, (referrer_stats.referred.u128(), &referrer));
+        referrer_stats.swaps += 1;
+        referrer_stats.referred += amount;
+        referrer_stats.fees_earned += amounts_to_distribute.referral;
+        referrer_stats.minted_earned += referral_minted;
+        REFERRERS.save(deps.storage, &referrer, &referrer_stats)?;
+        REFERRERS_BY_VOLUME.save(
+            deps.storage,
+            (referrer_stats.referred.u128(), &referrer),
+            &Empty {},
+        )?;
+
+        response = response.add_submessages(payout_submsg(
+            deps.storage,
+            &referrer,
+            Coin {
+                denom: config.accepted_denom.clone(),
+                amount: amounts_to_distribute.referral,
+            },
+        )?);
+        if !referral_minted.is_zero() {
+            response = response.add_message(TokenFactoryMsg::mint_contract_tokens(
+                product.denom.clone(),
+                referral_minted,
+                referrer.to_string(),
+            ));
+        }
+        response = response.add_event(
+            Event::new("referral")
+                .add_attribute("referrer", referrer)
+                .add_attribute("fees", amounts_to_distribute.referral)
+                .add_attribute("minted", referral_minted),
+        );
+    }
+
+    // Finalizes with the swap reaching the cap of the default product
+    if product.denom == config.factory_denom && is_minting_finished(&stats, &product) {
+        response = finalize(deps, &env, &config, product, amount_sent, response)?;
+    }
+
+    Ok(response)
+}
+
+pub fn receive_cw20(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    // Sent by the CW20 contract
+    let token = info.sender;
+    ensure!(
+        CW20_ALLOWLIST.has(deps.storage, &token),
+        ContractError::Cw20NotAllowed {
+            token: token.to_string()
+        }
     );
-    STATS.save(deps.storage, stats)?;
+    let sender = deps.api.addr_validate(&cw20_msg.sender)?;
+
+    match from_binary(&cw20_msg.msg)? {
+        ReceiveMsg::Swap { product } => {
+            let config = CONFIG.load(deps.storage)?;
+            ensure!(
+                config.enabled && !is_paused(deps.storage, Operation::Swap),
+                ContractError::SwapDisabled {}
+            );
+            swap_cw20(deps, env, token, sender, cw20_msg.amount, product)
+        }
+    }
+}
 
-    // Burn: currently keeps the Juno in the contract which will be burned with `Burn` msg later
-    // TODO: To be changed when the new burn module is live on Juno
-    TO_BURN.update(
+// Same guards, rate and distribution as `swap`, the shares are transferred and the burned share is burned
+fn swap_cw20(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    token: Addr,
+    sender: Addr,
+    amount: Uint128,
+    product: Option<String>,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    ensure!(
+        !amount.is_zero(),
+        ContractError::ZeroAmount {
+            field: "amount".to_string()
+        }
+    );
+    do_swap(
+        deps,
+        env,
+        SwapInput::Cw20(token),
+        amount,
+        sender,
+        SwapOptions {
+            product,
+            ..SwapOptions::default()
+        },
+    )
+}
+
+pub fn update_cw20_allowlist(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::ConfigManager)?;
+
+    for token in add {
+        let token = deps.api.addr_validate(&token)?;
+        CW20_ALLOWLIST.save(deps.storage, &token, &Empty {})?;
+    }
+    for token in remove {
+        let token = deps.api.addr_validate(&token)?;
+        CW20_ALLOWLIST.remove(deps.storage, &token);
+    }
+
+    Ok(Response::new().add_attribute("action", "update_cw20_allowlist"))
+}
+
+pub fn update_circuit_breaker(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    circuit_breaker: Option<CircuitBreaker>,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::ConfigManager)?;
+
+    if let Some(circuit_breaker) = circuit_breaker {
+        CIRCUIT_BREAKER.save(deps.storage, &circuit_breaker.validate(deps.api)?)?;
+    } else {
+        CIRCUIT_BREAKER.remove(deps.storage);
+    }
+
+    Ok(Response::default())
+}
+
+pub fn reset_circuit_breaker(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let guardian = CIRCUIT_BREAKER
+        .may_load(deps.storage)?
+        .and_then(|circuit_breaker| circuit_breaker.guardian);
+    ensure!(
+        has_role(deps.storage, &config, &info.sender, Role::Owner)
+            || guardian.as_deref() == Some(info.sender.as_str()),
+        ContractError::Unauthorized {}
+    );
+
+    // Starts a new window
+    SWAP_VOLUME.save(deps.storage, &SwapVolume::zero())?;
+
+    Ok(Response::new()
+        .add_event(Event::new("circuit_breaker_reset").add_attribute("sender", info.sender)))
+}
+
+pub fn grant_role(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    let address = deps.api.addr_validate(&address)?;
+    ROLES.save(deps.storage, (role.as_str(), &address), &Empty {})?;
+
+    Ok(Response::new()
+        .add_attribute("action", "grant_role")
+        .add_attribute("role", role.as_str())
+        .add_attribute("address", address))
+}
+
+pub fn revoke_role(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    role: Role,
+    address: String,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    let address = deps.api.addr_validate(&address)?;
+    ensure!(
+        ROLES.has(deps.storage, (role.as_str(), &address)),
+        ContractError::RoleNotGranted {}
+    );
+    ROLES.remove(deps.storage, (role.as_str(), &address));
+
+    Ok(Response::new()
+        .add_attribute("action", "revoke_role")
+        .add_attribute("role", role.as_str())
+        .add_attribute("address", address))
+}
+
+pub fn pause(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+    operations: Option<Vec<Operation>>,
+    reason: Option<String>,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Pauser)?;
+
+    let operations = operations.unwrap_or_else(Operation::all);
+    for operation in operations.iter() {
+        // Already paused operations keep their original reason
+        if !PAUSED.has(deps.storage, operation.as_str()) {
+            PAUSED.save(
+                deps.storage,
+                operation.as_str(),
+                &PauseInfo {
+                    reason: reason.clone(),
+                    paused_at: env.block.time,
+                    paused_by: info.sender.to_string(),
+                },
+            )?;
+        }
+    }
+
+    if operations.contains(&Operation::Swap) {
+        config.enabled = false;
+        CONFIG.save(deps.storage, &config)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "pause")
+        .add_attribute("operations", operations_attribute(&operations)))
+}
+
+pub fn unpause(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    operations: Option<Vec<Operation>>,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    let operations = operations.unwrap_or_else(Operation::all);
+    for operation in operations.iter() {
+        PAUSED.remove(deps.storage, operation.as_str());
+    }
+
+    if operations.contains(&Operation::Swap) {
+        config.enabled = true;
+        CONFIG.save(deps.storage, &config)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "unpause")
+        .add_attribute("operations", operations_attribute(&operations)))
+}
+
+fn operations_attribute(operations: &[Operation]) -> String {
+    operations
+        .iter()
+        .map(|operation| operation.as_str())
+        .collect::<Vec<&str>>()
+        .join(",")
+}
+
+pub fn set_denom_metadata(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    metadata: DenomMetadata,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    let metadata = metadata.validate(&config.factory_denom)?;
+    DENOM_METADATA.save(deps.storage, &metadata)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_denom_metadata")
+        .add_message(TokenFactoryMsg::SetMetadata {
+            metadata: metadata.to_token_factory(&config.factory_denom),
+            denom: config.factory_denom,
+        }))
+}
+
+pub fn create_product(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+    subdenom: String,
+    rate: Decimal,
+    cap: Uint128,
+    metadata: Option<DenomMetadata>,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    let product = Product::new(&env.contract.address, subdenom, rate, cap)?;
+    ensure!(
+        product.denom != config.factory_denom && !PRODUCTS.has(deps.storage, &product.denom),
+        ContractError::ProductAlreadyExists {}
+    );
+    PRODUCTS.save(deps.storage, &product.denom, &product)?;
+
+    let metadata = metadata
+        .map(|metadata| metadata.validate(&product.denom))
+        .transpose()?
+        .map(|metadata| metadata.to_token_factory(&product.denom));
+
+    PENDING_DENOM.save(deps.storage, &product.denom)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_product")
+        .add_attribute("denom", product.denom)
+        .add_submessage(SubMsg::reply_on_success(
+            TokenFactoryMsg::CreateDenom {
+                subdenom: product.subdenom,
+                metadata,
+            },
+            CREATE_DENOM_REPLY_ID,
+        )))
+}
+
+pub fn change_denom_admin(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+    denom: Option<String>,
+    new_admin: String,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    let mut product = load_product(deps.storage, &config, denom)?;
+    ensure!(
+        product.admin_transferred_to.is_none(),
+        ContractError::DenomAdminTransferred {}
+    );
+    // Swaps would fail on minting once the contract isn't the admin anymore
+    ensure!(
+        product.minted >= product.cap
+            || !config.enabled
+            || is_paused(deps.storage, Operation::Swap),
+        ContractError::MintingNotFinished {}
+    );
+    // Vesting positions and locks are minted when claimed or withdrawn
+    ensure!(
+        product.vesting.is_zero(),
+        ContractError::VestingOutstanding {
+            amount: product.vesting
+        }
+    );
+    ensure!(
+        product.locked.is_zero(),
+        ContractError::LocksOutstanding {
+            amount: product.locked
+        }
+    );
+
+    let new_admin = deps.api.addr_validate(&new_admin)?;
+    ensure!(
+        new_admin != env.contract.address,
+        ContractError::InvalidDenomAdmin {}
+    );
+    product.admin_transferred_to = Some(new_admin.to_string());
+    PRODUCTS.save(deps.storage, &product.denom, &product)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "change_denom_admin")
+        .add_attribute("denom", product.denom.clone())
+        .add_attribute("new_admin", new_admin.clone())
+        .add_message(TokenFactoryMsg::ChangeAdmin {
+            denom: product.denom,
+            new_admin_address: new_admin.to_string(),
+        }))
+}
+
+pub fn burn_from(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+    denom: Option<String>,
+    amount: Uint128,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    ensure!(
+        !amount.is_zero(),
+        ContractError::ZeroAmount {
+            field: "amount".to_string()
+        }
+    );
+    let mut product = load_product(deps.storage, &config, denom)?;
+    ensure!(
+        product.admin_transferred_to.is_none(),
+        ContractError::DenomAdminTransferred {}
+    );
+    // Only what holders handed to the contract can be burned
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &product.denom)?
+        .amount;
+    ensure!(
+        amount <= balance,
+        ContractError::BurnExceedsBalance { balance, amount }
+    );
+
+    product.burned += amount;
+    PRODUCTS.save(deps.storage, &product.denom, &product)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "burn_from")
+        .add_attribute("denom", product.denom.clone())
+        .add_attribute("amount", amount)
+        .add_message(TokenFactoryMsg::BurnTokens {
+            denom: product.denom,
+            amount,
+            burn_from_address: env.contract.address.to_string(),
+        }))
+}
+
+pub fn execute_finalize(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure!(
+        FINALIZED_AT.may_load(deps.storage)?.is_none(),
+        ContractError::SwapFinalized {}
+    );
+    let stats = STATS.load(deps.storage)?;
+    let product = load_product(deps.storage, &config, None)?;
+    ensure!(
+        is_minting_finished(&stats, &product),
+        ContractError::MintingNotFinished {}
+    );
+
+    finalize(
+        deps,
+        &env,
+        &config,
+        product,
+        Uint128::zero(),
+        Response::new(),
+    )
+}
+
+// Sweeps what is left of the accepted denom to the buckets and renounces the admin of the default
+// product. `amount_sent` is what the response already sends from the contract balance
+fn finalize(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: &Env,
+    config: &Config,
+    product: Product,
+    amount_sent: Uint128,
+    mut response: Response<TokenFactoryMsg>,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    FINALIZED_AT.save(deps.storage, &env.block.time)?;
+
+    // The Juno to burn, the claims and the redemption reserve stay in the contract
+    let to_burn = TO_BURN.load(deps.storage)?;
+    let claims_total = CLAIMS_TOTAL.may_load(deps.storage)?.unwrap_or_default();
+    let reserve = RESERVE.may_load(deps.storage)?.unwrap_or_default();
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &config.accepted_denom)?;
+    let remaining = balance
+        .amount
+        .saturating_sub(to_burn.amount)
+        .saturating_sub(claims_total)
+        .saturating_sub(reserve)
+        .saturating_sub(amount_sent);
+    let amounts_to_sweep = compute_amounts_to_sweep(config, remaining);
+
+    let mut stats = STATS.load(deps.storage)?;
+    let burn_msg = route_burn(
         deps.storage,
-        |mut updated_to_burn| -> Result<_, ContractError> {
-            updated_to_burn.amount += amounts_to_distribute.burned;
-            Ok(updated_to_burn)
+        &mut stats,
+        Coin {
+            denom: config.accepted_denom.clone(),
+            amount: amounts_to_sweep.burned,
         },
     )?;
+    STATS.save(deps.storage, &stats)?;
+    if let Some(burn_msg) = burn_msg {
+        response = response.add_message(burn_msg);
+    }
 
-    // Dev funds
-    response = response.add_message(cosmwasm_std::BankMsg::Send {
-        to_address: config.balance_development_fund_addr.clone(),
-        amount: vec![Coin {
-            denom: config.accepted_denom.clone(),
-            amount: amounts_to_distribute.balance_dev_fund,
-        }],
-    });
-
-    // Vesting
-    response = response.add_message(cosmwasm_std::BankMsg::Send {
-        to_address: config.juno_development_fund_addr.clone(),
-        amount: vec![Coin {
-            denom: config.accepted_denom.clone(),
-            amount: amounts_to_distribute.juno_dev_fund,
-        }],
-    });
-
-    // Dev
-    response = response.add_message(cosmwasm_std::BankMsg::Send {
-        to_address: config.dev_addr.clone(),
-        amount: vec![Coin {
-            denom: config.accepted_denom.clone(),
-            amount: amounts_to_distribute.dev,
-        }],
-    });
+    for (address, amount) in [
+        (
+            &config.balance_development_fund_addr,
+            amounts_to_sweep.balance_dev_fund,
+        ),
+        (
+            &config.juno_development_fund_addr,
+            amounts_to_sweep.juno_dev_fund,
+        ),
+        (&config.dev_addr, amounts_to_sweep.dev),
+    ] {
+        response = response.add_submessages(payout_submsg(
+            deps.storage,
+            address,
+            Coin {
+                denom: config.accepted_denom.clone(),
+                amount,
+            },
+        )?);
+    }
+
+    // Renounced once the last vesting position or lock is minted otherwise
+    if product.unminted().is_zero() {
+        response = renounce_admin(deps.storage, product, response)?;
+    }
+
+    Ok(response.add_event(
+        Event::new("finalized")
+            .add_attribute("swept", remaining)
+            .add_attribute("to_burn", TO_BURN.load(deps.storage)?.amount),
+    ))
+}
+
+// Renounced to an address nobody controls, nothing can be minted anymore
+fn renounce_admin(
+    storage: &mut dyn Storage,
+    mut product: Product,
+    response: Response<TokenFactoryMsg>,
+) -> StdResult<Response<TokenFactoryMsg>> {
+    if product.admin_transferred_to.is_some() {
+        return Ok(response);
+    }
+    product.admin_transferred_to = Some(RENOUNCED_ADMIN.to_string());
+    PRODUCTS.save(storage, &product.denom, &product)?;
+    Ok(response.add_message(TokenFactoryMsg::ChangeAdmin {
+        denom: product.denom,
+        new_admin_address: RENOUNCED_ADMIN.to_string(),
+    }))
+}
 
+pub fn claim(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
     ensure!(
-        token_amount_to_send + stats.distributed <= BALANCE_MAX_SUPPLY,
-        ContractError::MaxSupplyReached {}
+        !is_paused(deps.storage, Operation::Claims),
+        ContractError::OperationPaused {
+            operation: Operation::Claims.as_str().to_string()
+        }
     );
-    let mint_tokens_msg = TokenFactoryMsg::mint_contract_tokens(
-        config.factory_denom,
-        token_amount_to_send,
-        info.sender.to_string(),
+
+    let amount = CLAIMS
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    ensure!(!amount.is_zero(), ContractError::NothingToClaim {});
+    CLAIMS.remove(deps.storage, &info.sender);
+    CLAIMS_TOTAL.update(deps.storage, |total| -> StdResult<_> {
+        Ok(total.checked_sub(amount)?)
+    })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "claim")
+        .add_attribute("amount", amount)
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![Coin {
+                denom: config.accepted_denom,
+                amount,
+            }],
+        }))
+}
+
+pub fn claim_vested(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure!(
+        !is_paused(deps.storage, Operation::Claims),
+        ContractError::OperationPaused {
+            operation: Operation::Claims.as_str().to_string()
+        }
     );
 
-    response = response.add_message(mint_tokens_msg);
+    let positions = VESTING_POSITIONS
+        .prefix(&info.sender)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, position)| position))
+        .collect::<StdResult<Vec<VestingPosition>>>()?;
+    let mut claimed: Vec<Coin> = vec![];
+    for mut position in positions {
+        let amount = position.claimable(env.block.time);
+        if amount.is_zero() {
+            continue;
+        }
+        position.claimed += amount;
+        if position.claimed == position.amount {
+            VESTING_POSITIONS.remove(deps.storage, (&info.sender, position.id));
+        } else {
+            VESTING_POSITIONS.save(deps.storage, (&info.sender, position.id), &position)?;
+        }
+        match claimed.iter_mut().find(|coin| coin.denom == position.denom) {
+            Some(coin) => coin.amount += amount,
+            None => claimed.push(Coin {
+                denom: position.denom,
+                amount,
+            }),
+        }
+    }
+    ensure!(!claimed.is_empty(), ContractError::NothingToClaim {});
+
+    let finalized = FINALIZED_AT.may_load(deps.storage)?.is_some();
+    let mut response = Response::new().add_attribute("action", "claim_vested");
+    for coin in claimed {
+        let mut product = load_product(deps.storage, &config, Some(coin.denom.clone()))?;
+        product.vesting -= coin.amount;
+        PRODUCTS.save(deps.storage, &product.denom, &product)?;
+        response = response
+            .add_attribute("claimed", coin.to_string())
+            .add_message(TokenFactoryMsg::mint_contract_tokens(
+                coin.denom,
+                coin.amount,
+                info.sender.to_string(),
+            ));
+        if finalized && product.denom == config.factory_denom && product.unminted().is_zero() {
+            response = renounce_admin(deps.storage, product, response)?;
+        }
+    }
 
     Ok(response)
 }
+
+pub fn withdraw(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure!(
+        !is_paused(deps.storage, Operation::Claims),
+        ContractError::OperationPaused {
+            operation: Operation::Claims.as_str().to_string()
+        }
+    );
+
+    let unlocked = LOCKS
+        .prefix(&info.sender)
+        .range(deps.storage, None, None, Order::Ascending)
+        .filter(|item| {
+            item.as_ref()
+                .map_or(true, |(_, lock)| lock.unlocks_at <= env.block.time)
+        })
+        .map(|item| item.map(|(_, lock)| lock))
+        .collect::<StdResult<Vec<Lock>>>()?;
+    ensure!(!unlocked.is_empty(), ContractError::NothingToWithdraw {});
+
+    let mut withdrawn: Vec<Coin> = vec![];
+    for lock in unlocked {
+        LOCKS.remove(deps.storage, (&info.sender, lock.id));
+        match withdrawn.iter_mut().find(|coin| coin.denom == lock.denom) {
+            Some(coin) => coin.amount += lock.amount,
+            None => withdrawn.push(Coin {
+                denom: lock.denom,
+                amount: lock.amount,
+            }),
+        }
+    }
+
+    let finalized = FINALIZED_AT.may_load(deps.storage)?.is_some();
+    let mut response = Response::new().add_attribute("action", "withdraw");
+    for coin in withdrawn {
+        let mut product = load_product(deps.storage, &config, Some(coin.denom.clone()))?;
+        product.locked -= coin.amount;
+        PRODUCTS.save(deps.storage, &product.denom, &product)?;
+        response = response
+            .add_attribute("withdrawn", coin.to_string())
+            .add_message(TokenFactoryMsg::mint_contract_tokens(
+                coin.denom,
+                coin.amount,
+                info.sender.to_string(),
+            ));
+        if finalized && product.denom == config.factory_denom && product.unminted().is_zero() {
+            response = renounce_admin(deps.storage, product, response)?;
+        }
+    }
+
+    Ok(response)
+}
+
+pub fn update_lockup(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    lockup: Lockup,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::ConfigManager)?;
+
+    LOCKUP.save(deps.storage, &lockup.validate()?)?;
+
+    Ok(Response::new().add_attribute("action", "update_lockup"))
+}
+
+pub fn update_vesting(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    vesting: Vesting,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::ConfigManager)?;
+
+    VESTING.save(deps.storage, &vesting.validate()?)?;
+
+    Ok(Response::new().add_attribute("action", "update_vesting"))
+}
+
+pub fn create_denom_reply(
+    deps: DepsMut<TokenFactoryQuery>,
+    result: SubMsgResult,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let expected_denom = PENDING_DENOM.load(deps.storage)?;
+    PENDING_DENOM.remove(deps.storage);
+
+    let sub_msg_response = result.into_result().map_err(StdError::generic_err)?;
+    let denom = created_denom(&sub_msg_response).unwrap_or_else(|| expected_denom.clone());
+
+    // Stores the denom actually created by the token factory
+    if denom != expected_denom {
+        let mut product = PRODUCTS.load(deps.storage, &expected_denom)?;
+        product.denom = denom.clone();
+        PRODUCTS.remove(deps.storage, &expected_denom);
+        PRODUCTS.save(deps.storage, &denom, &product)?;
+
+        let mut config = CONFIG.load(deps.storage)?;
+        if config.factory_denom == expected_denom {
+            config.factory_denom = denom.clone();
+            CONFIG.save(deps.storage, &config)?;
+        }
+    }
+
+    Ok(Response::new().add_event(Event::new("denom_created").add_attribute("denom", denom)))
+}
+
+// The denom from the `MsgCreateDenomResponse` data, or from the token factory event
+fn created_denom(sub_msg_response: &SubMsgResponse) -> Option<String> {
+    sub_msg_response
+        .data
+        .as_ref()
+        .and_then(|data| parse_execute_response_data(data).ok())
+        .and_then(|response| response.data)
+        .and_then(|denom| String::from_utf8(denom.0).ok())
+        .or_else(|| {
+            sub_msg_response
+                .events
+                .iter()
+                .filter(|event| event.ty == "create_denom")
+                .flat_map(|event| event.attributes.iter())
+                .find(|attribute| attribute.key == "new_token_denom")
+                .map(|attribute| attribute.value.clone())
+        })
+        .filter(|denom| denom.starts_with("factory/"))
+}
+
+pub fn payout_reply(
+    deps: DepsMut<TokenFactoryQuery>,
+    id: u64,
+    result: SubMsgResult,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let payout = PENDING_PAYOUTS
+        .may_load(deps.storage, id)?
+        .ok_or(ContractError::UnknownReplyId { id })?;
+    PENDING_PAYOUTS.remove(deps.storage, id);
+
+    match result {
+        SubMsgResult::Ok(_) => Ok(Response::default()),
+        // The swap goes through, the recipient can claim the amount later
+        SubMsgResult::Err(error) => {
+            CLAIMS.update(deps.storage, &payout.recipient, |claim| -> StdResult<_> {
+                Ok(claim.unwrap_or_default() + payout.amount.amount)
+            })?;
+            let claims_total = CLAIMS_TOTAL.may_load(deps.storage)?.unwrap_or_default();
+            CLAIMS_TOTAL.save(deps.storage, &(claims_total + payout.amount.amount))?;
+
+            Ok(Response::new().add_event(
+                Event::new("payout_failed")
+                    .add_attribute("recipient", payout.recipient)
+                    .add_attribute("amount", payout.amount.to_string())
+                    .add_attribute("error", error),
+            ))
+        }
+    }
+}
+
+pub fn ibc_forward_reply(
+    deps: DepsMut<TokenFactoryQuery>,
+    result: SubMsgResult,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let forward = PENDING_IBC_FORWARD.load(deps.storage)?;
+    PENDING_IBC_FORWARD.remove(deps.storage);
+
+    match result {
+        // Kept for the ibc-hooks callback, with the sequence of the `MsgTransferResponse`. The swap
+        // reverts without it, the callback could not be matched to the forward
+        SubMsgResult::Ok(sub_msg_response) => {
+            let sequence = sub_msg_response
+                .data
+                .as_ref()
+                .and_then(|data| transfer_sequence(data))
+                .ok_or(ContractError::InvalidTransferResponse {})?;
+            IBC_FORWARDS.save(deps.storage, (&forward.channel, sequence), &forward)?;
+            Ok(Response::new().add_event(
+                Event::new("ibc_forward")
+                    .add_attribute("channel", forward.channel.clone())
+                    .add_attribute("amount", forward.amount.to_string())
+                    .add_attribute("sequence", sequence.to_string()),
+            ))
+        }
+        // The swap goes through, the product is sent to the recipient instead
+        SubMsgResult::Err(error) => Ok(refund_ibc_forward(forward, &error)),
+    }
+}
+
+pub fn ibc_lifecycle_complete(
+    deps: DepsMut<TokenFactoryQuery>,
+    lifecycle: IbcLifecycleComplete,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let (channel, sequence, failure) = match lifecycle {
+        IbcLifecycleComplete::IbcAck {
+            channel,
+            sequence,
+            ack,
+            success,
+        } => (channel, sequence, (!success).then_some(ack)),
+        IbcLifecycleComplete::IbcTimeout { channel, sequence } => {
+            (channel, sequence, Some("timeout".to_string()))
+        }
+    };
+    let forward = IBC_FORWARDS
+        .may_load(deps.storage, (&channel, sequence))?
+        .ok_or_else(|| ContractError::UnknownIbcPacket {
+            channel: channel.clone(),
+            sequence,
+        })?;
+    IBC_FORWARDS.remove(deps.storage, (&channel, sequence));
+
+    // The transfer module sent the product back to the contract
+    match failure {
+        Some(reason) => Ok(refund_ibc_forward(forward, &reason)),
+        None => Ok(Response::new().add_event(
+            Event::new("ibc_forward_acknowledged")
+                .add_attribute("channel", channel)
+                .add_attribute("sequence", sequence.to_string()),
+        )),
+    }
+}
+
+fn refund_ibc_forward(forward: PendingIbcForward, reason: &str) -> Response<TokenFactoryMsg> {
+    Response::new()
+        .add_message(BankMsg::Send {
+            to_address: forward.refund_to.to_string(),
+            amount: vec![forward.amount.clone()],
+        })
+        .add_event(
+            Event::new("ibc_forward_refunded")
+                .add_attribute("recipient", forward.refund_to)
+                .add_attribute("channel", forward.channel)
+                .add_attribute("amount", forward.amount.to_string())
+                .add_attribute("reason", reason),
+        )
+}
+
+pub fn update_burn_destination(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    burn_destination: BurnDestination,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::ConfigManager)?;
+
+    BURN_DESTINATION.save(deps.storage, &burn_destination.validate(deps.api)?)?;
+
+    Ok(Response::new().add_attribute("action", "update_burn_destination"))
+}
+
+pub fn update_burn_crank(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    burn_crank: BurnCrank,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::ConfigManager)?;
+
+    BURN_CRANK.save(deps.storage, &burn_crank.validate()?)?;
+
+    Ok(Response::new().add_attribute("action", "update_burn_crank"))
+}
+
+pub fn update_referral(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    referral: Referral,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::ConfigManager)?;
+
+    REFERRAL.save(deps.storage, &referral.validate()?)?;
+
+    Ok(Response::new().add_attribute("action", "update_referral"))
+}
+
+// `Burn` burns everything right away, `CrankBurn` waits for the thresholds and tips the caller
+pub fn burn(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+    crank: bool,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    ensure!(
+        !is_paused(deps.storage, Operation::Burn),
+        ContractError::OperationPaused {
+            operation: Operation::Burn.as_str().to_string()
+        }
+    );
+    let burn_crank = BURN_CRANK
+        .may_load(deps.storage)?
+        .unwrap_or_else(BurnCrank::disabled);
+    ensure!(burn_crank.enabled, ContractError::NotImplementedYet {});
+
+    let mut to_burn = TO_BURN.load(deps.storage)?;
+    let last_burn = BURN_HISTORY
+        .range(deps.storage, None, None, Order::Descending)
+        .next()
+        .transpose()?;
+    let mut tip = Uint128::zero();
+    if crank {
+        ensure!(
+            burn_crank.is_ready(
+                to_burn.amount,
+                last_burn.as_ref().map(|(_, record)| record.burned_at),
+                env.block.time
+            ),
+            ContractError::BurnCrankNotReady {}
+        );
+        tip = Decimal::permille(burn_crank.tip_permille_u64) * to_burn.amount;
+    } else {
+        ensure!(
+            !to_burn.amount.is_zero(),
+            ContractError::ZeroAmount {
+                field: "to_burn".to_string()
+            }
+        );
+    }
+
+    let burned = Coin {
+        denom: to_burn.denom.clone(),
+        amount: to_burn.amount - tip,
+    };
+    to_burn.amount = Uint128::zero();
+    TO_BURN.save(deps.storage, &to_burn)?;
+
+    record_burn(deps.storage, &env, &info, burned.clone(), tip)?;
+
+    let mut response = Response::new()
+        .add_attribute("action", "burn")
+        .add_attribute("amount", burned.to_string())
+        .add_attribute("tip", tip)
+        .add_message(BankMsg::Burn {
+            amount: vec![burned],
+        });
+    if !tip.is_zero() {
+        response = response.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![Coin {
+                denom: to_burn.denom,
+                amount: tip,
+            }],
+        });
+    }
+
+    Ok(response)
+}
+
+fn record_burn(
+    storage: &mut dyn Storage,
+    env: &Env,
+    info: &MessageInfo,
+    amount: Coin,
+    tip: Uint128,
+) -> StdResult<()> {
+    let id = BURN_HISTORY
+        .keys(storage, None, None, Order::Descending)
+        .next()
+        .transpose()?
+        .map(|id| id + 1)
+        .unwrap_or_default();
+    BURN_HISTORY.save(
+        storage,
+        id,
+        &BurnRecord {
+            id,
+            amount,
+            tip,
+            burned_by: info.sender.to_string(),
+            burned_at: env.block.time,
+        },
+    )
+}
+
+// Burns again the Juno which was sent to the dead address before 0.1.1 and got reminted
+pub fn settle_snapshot_debt(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+    let mut settlement = SNAPSHOT_SETTLEMENT
+        .may_load(deps.storage)?
+        .unwrap_or_else(SnapshotSettlement::zero);
+    ensure!(
+        settlement.enabled,
+        ContractError::SnapshotSettlementDisabled {}
+    );
+
+    let snapshot = BURNED_REMINTED_SNAPSHOT
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NoSnapshotDebt {})?;
+    let remaining = snapshot.amount.saturating_sub(settlement.settled);
+    ensure!(!remaining.is_zero(), ContractError::NoSnapshotDebt {});
+
+    validate_coin_received(&snapshot.denom, &info)?;
+    let amount = info.funds[0].clone();
+    ensure!(
+        amount.amount <= remaining,
+        ContractError::SnapshotDebtExceeded { remaining }
+    );
+
+    settlement.settled += amount.amount;
+    settlement.last_settled_at = Some(env.block.time);
+    // Kept out of the burn history, which paces the burn crank
+    SNAPSHOT_SETTLEMENT.save(deps.storage, &settlement)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "settle_snapshot_debt")
+        .add_attribute("amount", amount.to_string())
+        .add_attribute("remaining", remaining - amount.amount)
+        .add_message(BankMsg::Burn {
+            amount: vec![amount],
+        }))
+}
+
+pub fn update_snapshot_settlement(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    enabled: bool,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    let mut settlement = SNAPSHOT_SETTLEMENT
+        .may_load(deps.storage)?
+        .unwrap_or_else(SnapshotSettlement::zero);
+    settlement.enabled = enabled;
+    SNAPSHOT_SETTLEMENT.save(deps.storage, &settlement)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_snapshot_settlement")
+        .add_attribute("enabled", enabled.to_string()))
+}
+
+pub fn update_redemption(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    redemption: Redemption,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    let product = load_product(deps.storage, &config, None)?;
+    REDEMPTION.save(deps.storage, &redemption.validate(product.rate)?)?;
+
+    Ok(Response::new().add_attribute("action", "update_redemption"))
+}
+
+pub fn fund_reserve(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    validate_coin_received(&config.accepted_denom, &info)?;
+
+    let reserve = RESERVE.may_load(deps.storage)?.unwrap_or_default() + info.funds[0].amount;
+    RESERVE.save(deps.storage, &reserve)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "fund_reserve")
+        .add_attribute("amount", info.funds[0].amount)
+        .add_attribute("reserve", reserve))
+}
+
+pub fn withdraw_reserve(
+    deps: DepsMut<TokenFactoryQuery>,
+    info: MessageInfo,
+    amount: Uint128,
+    to: String,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    ensure!(
+        !amount.is_zero(),
+        ContractError::ZeroAmount {
+            field: "amount".to_string()
+        }
+    );
+    let to = deps.api.addr_validate(&to)?;
+    let reserve = RESERVE.may_load(deps.storage)?.unwrap_or_default();
+    ensure!(
+        amount <= reserve,
+        ContractError::InsufficientReserve {
+            reserve,
+            required: amount
+        }
+    );
+    RESERVE.save(deps.storage, &(reserve - amount))?;
+
+    Ok(Response::new()
+        .add_attribute("action", "withdraw_reserve")
+        .add_attribute("amount", amount)
+        .add_attribute("to", to.clone())
+        .add_message(BankMsg::Send {
+            to_address: to.to_string(),
+            amount: vec![Coin {
+                denom: config.accepted_denom,
+                amount,
+            }],
+        }))
+}
+
+pub fn redeem(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let redemption = REDEMPTION
+        .may_load(deps.storage)?
+        .unwrap_or_else(Redemption::disabled);
+    ensure!(
+        redemption.is_open(env.block.time),
+        ContractError::RedemptionClosed {}
+    );
+    validate_coin_received(&config.factory_denom, &info)?;
+    let mut product = load_product(deps.storage, &config, None)?;
+
+    let amount = info.funds[0].amount;
+    let payout = amount * redemption.rate;
+    ensure!(
+        !payout.is_zero(),
+        ContractError::ZeroAmount {
+            field: "payout".to_string()
+        }
+    );
+    let reserve = RESERVE.may_load(deps.storage)?.unwrap_or_default();
+    ensure!(
+        payout <= reserve,
+        ContractError::InsufficientReserve {
+            reserve,
+            required: payout
+        }
+    );
+    RESERVE.save(deps.storage, &(reserve - payout))?;
+
+    let mut stats = STATS.load(deps.storage)?;
+    stats.redeemed += amount;
+    stats.redemption_payouts += payout;
+    STATS.save(deps.storage, &stats)?;
+
+    let mut response = Response::new();
+    // Burned by the contract as the token factory admin, held once the admin is handed over
+    if product.admin_transferred_to.is_none() {
+        product.burned += amount;
+        PRODUCTS.save(deps.storage, &product.denom, &product)?;
+        response = response.add_message(TokenFactoryMsg::BurnTokens {
+            denom: product.denom,
+            amount,
+            burn_from_address: env.contract.address.to_string(),
+        });
+    } else {
+        let redeemed_held = REDEEMED_HELD.may_load(deps.storage)?.unwrap_or_default();
+        REDEEMED_HELD.save(deps.storage, &(redeemed_held + amount))?;
+    }
+
+    Ok(response
+        .add_attribute("action", "redeem")
+        .add_attribute("amount", amount)
+        .add_attribute("payout", payout)
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![Coin {
+                denom: config.accepted_denom,
+                amount: payout,
+            }],
+        }))
+}
+
+pub fn recover_funds(
+    deps: DepsMut<TokenFactoryQuery>,
+    env: Env,
+    info: MessageInfo,
+    denom: String,
+    amount: Uint128,
+    to: String,
+) -> Result<Response<TokenFactoryMsg>, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    ensure_role(deps.storage, &config, &info.sender, Role::Owner)?;
+
+    ensure!(
+        !amount.is_zero(),
+        ContractError::ZeroAmount {
+            field: "amount".to_string()
+        }
+    );
+    let to = deps.api.addr_validate(&to)?;
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, denom.clone())?;
+    let recoverable = recoverable_amount(deps.storage, &config, &balance)?;
+    ensure!(
+        amount <= recoverable,
+        ContractError::NotRecoverable { recoverable }
+    );
+
+    Ok(Response::new()
+        .add_attribute("action", "recover_funds")
+        .add_attribute("denom", denom.clone())
+        .add_attribute("amount", amount)
+        .add_attribute("to", to.clone())
+        .add_message(BankMsg::Send {
+            to_address: to.to_string(),
+            amount: vec![Coin { denom, amount }],
+        }))
+}
@@ -1,7 +1,25 @@
+use crate::constants::{
+    BALANCE_MAX_SUPPLY, IBC_FORWARD_REPLY_ID, IBC_HOOKS_SENDER_PREFIX, JUNO_MAX_SUPPLY,
+    MSG_TRANSFER_TYPE_URL, PAYOUT_REPLY_ID_START, TRANSFER_PORT,
+};
 use crate::error::ContractError;
-use crate::state::Config;
+use crate::state::{
+    BurnDestination, Config, IbcForward, InvariantViolation, LockBonus, Lockup, Operation, Payout,
+    PendingIbcForward, Product, Redemption, RedemptionStatus, Role, Statistics, SupplyInfo,
+    SwapSimulation, BURNED_REMINTED_SNAPSHOT, BURN_DESTINATION, CLAIMS_TOTAL, CONFIG, LOCKUP,
+    NEXT_PAYOUT_ID, PAUSED, PENDING_IBC_FORWARD, PENDING_PAYOUTS, PRODUCTS, REDEEMED_HELD,
+    REDEMPTION, RESERVE, ROLES, SNAPSHOT_SETTLEMENT, STATS, TO_BURN,
+};
+use bech32::{ToBase32, Variant};
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{ensure, Decimal, MessageInfo, Uint128};
+use cosmwasm_std::{
+    ensure, to_binary, Addr, BankMsg, Coin, CosmosMsg, Decimal, Deps, DistributionMsg, Env,
+    MessageInfo, Order, QuerierWrapper, StdError, StdResult, Storage, SubMsg, Timestamp, Uint128,
+    Uint64, WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
+use sha2::{Digest, Sha256};
+use token_bindings::{TokenFactoryMsg, TokenFactoryQuery};
 
 #[cw_serde]
 pub struct AmountsToDistribute {
@@ -9,6 +27,7 @@ pub struct AmountsToDistribute {
     pub balance_dev_fund: Uint128,
     pub juno_dev_fund: Uint128,
     pub dev: Uint128,
+    pub referral: Uint128,
 }
 
 impl AmountsToDistribute {
@@ -18,10 +37,11 @@ impl AmountsToDistribute {
             balance_dev_fund: Uint128::zero(),
             juno_dev_fund: Uint128::zero(),
             dev: Uint128::zero(),
+            referral: Uint128::zero(),
         }
     }
     pub fn total_value(&self) -> Uint128 {
-        self.dev + self.balance_dev_fund + self.juno_dev_fund + self.burned
+        self.dev + self.balance_dev_fund + self.juno_dev_fund + self.burned + self.referral
     }
 }
 
@@ -30,16 +50,29 @@ pub fn validate_coin_received(
     info: &MessageInfo,
 ) -> Result<(), ContractError> {
     ensure!(
-        info.funds.len() == 1,
-        ContractError::InvalidFundsReceived {}
+        info.funds.len() <= 1,
+        ContractError::MultipleCoins {
+            expected: accepted_denom.clone(),
+            received: info
+                .funds
+                .iter()
+                .map(|coin| coin.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+        }
     );
     ensure!(
-        &info.funds[0].denom == accepted_denom,
-        ContractError::InvalidFundsReceived {}
+        !info.funds.is_empty() && info.funds[0].amount > Uint128::zero(),
+        ContractError::ZeroAmount {
+            field: "funds".to_string()
+        }
     );
     ensure!(
-        info.funds[0].amount > Uint128::zero(),
-        ContractError::InvalidFundsReceived {}
+        &info.funds[0].denom == accepted_denom,
+        ContractError::WrongDenom {
+            expected: accepted_denom.clone(),
+            received: info.funds[0].denom.clone(),
+        }
     );
     Ok(())
 }
@@ -64,3 +97,538 @@ pub fn compute_amounts_to_distribute(
 
     Ok(amounts_to_send)
 }
+
+pub fn has_role(storage: &dyn Storage, config: &Config, address: &Addr, role: Role) -> bool {
+    *address == config.contract_owner
+        || ROLES.has(storage, (Role::Owner.as_str(), address))
+        || ROLES.has(storage, (role.as_str(), address))
+}
+
+pub fn ensure_role(
+    storage: &dyn Storage,
+    config: &Config,
+    address: &Addr,
+    role: Role,
+) -> Result<(), ContractError> {
+    ensure!(
+        has_role(storage, config, address, role),
+        ContractError::Unauthorized {}
+    );
+    Ok(())
+}
+
+pub fn is_paused(storage: &dyn Storage, operation: Operation) -> bool {
+    PAUSED.has(storage, operation.as_str())
+}
+
+pub fn default_product(config: &Config, subdenom: &str, minted: Uint128) -> Product {
+    Product {
+        subdenom: subdenom.to_string(),
+        denom: config.factory_denom.clone(),
+        rate: Decimal::from_ratio(BALANCE_MAX_SUPPLY, JUNO_MAX_SUPPLY),
+        cap: BALANCE_MAX_SUPPLY,
+        minted,
+        admin_transferred_to: None,
+        vesting: Uint128::zero(),
+        locked: Uint128::zero(),
+        burned: Uint128::zero(),
+    }
+}
+
+// Loads a product by denom, the default product when `None`
+pub fn load_product(
+    storage: &dyn Storage,
+    config: &Config,
+    denom: Option<String>,
+) -> Result<Product, ContractError> {
+    let denom = denom.unwrap_or_else(|| config.factory_denom.clone());
+    match PRODUCTS.may_load(storage, &denom)? {
+        Some(product) => Ok(product),
+        // Contracts instantiated before the registry only have the default product
+        None if denom == config.factory_denom => Ok(legacy_default_product(storage, config)?),
+        None => Err(ContractError::UnknownProduct {}),
+    }
+}
+
+fn legacy_default_product(storage: &dyn Storage, config: &Config) -> StdResult<Product> {
+    let subdenom = config
+        .factory_denom
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let stats = STATS.load(storage)?;
+    Ok(default_product(config, &subdenom, stats.distributed))
+}
+
+// Splits an amount between the distribution buckets, the rounding remainder going to the burn
+pub fn compute_amounts_to_sweep(config: &Config, amount: Uint128) -> AmountsToDistribute {
+    let mut amounts_to_send = AmountsToDistribute::init();
+
+    amounts_to_send.dev = Decimal::permille(config.dev_fees_permille_u64) * amount;
+    amounts_to_send.balance_dev_fund =
+        Decimal::permille(config.balance_development_fund_permille_u64) * amount;
+    amounts_to_send.juno_dev_fund =
+        Decimal::permille(config.juno_development_fund_permille_u64) * amount;
+    amounts_to_send.burned = amount
+        - amounts_to_send.dev
+        - amounts_to_send.balance_dev_fund
+        - amounts_to_send.juno_dev_fund;
+
+    amounts_to_send
+}
+
+// Nothing more can be swapped for the default product once its cap or the Juno max supply is reached
+pub fn is_minting_finished(stats: &Statistics, product: &Product) -> bool {
+    product.minted >= product.cap || stats.received >= JUNO_MAX_SUPPLY
+}
+
+// Bonus of the lockup schedule for `lock_duration`, `None` without a lock
+pub fn lock_bonus(
+    storage: &dyn Storage,
+    lock_duration: Option<u64>,
+) -> Result<Option<LockBonus>, ContractError> {
+    let lock_duration = match lock_duration {
+        Some(lock_duration) => lock_duration,
+        None => return Ok(None),
+    };
+    let lockup = LOCKUP.may_load(storage)?.unwrap_or_else(Lockup::disabled);
+    let bonus = lockup
+        .bonus(lock_duration)
+        .ok_or(ContractError::NoLockBonus { lock_duration })?;
+    // Not locked past the longest scheduled lock
+    let longest = lockup
+        .bonuses
+        .last()
+        .map_or(0, |longest_bonus| longest_bonus.duration);
+    ensure!(
+        lock_duration <= longest,
+        ContractError::LockTooLong {
+            lock_duration,
+            longest
+        }
+    );
+    Ok(Some(bonus.clone()))
+}
+
+// Checked, the durations are taken from the messages
+pub fn checked_plus_seconds(time: Timestamp, seconds: u64) -> StdResult<Timestamp> {
+    let nanos = Uint64::new(seconds)
+        .checked_mul(Uint64::new(1_000_000_000))?
+        .checked_add(Uint64::new(time.nanos()))?;
+    Ok(Timestamp::from_nanos(nanos.u64()))
+}
+
+pub fn simulate_swap(
+    deps: Deps<TokenFactoryQuery>,
+    env: &Env,
+    product: &Product,
+    amount: Uint128,
+    lock_duration: Option<u64>,
+) -> Result<SwapSimulation, ContractError> {
+    let token_amount = amount * product.rate;
+    let bonus = lock_bonus(deps.storage, lock_duration)?
+        .map(|bonus| Decimal::permille(bonus.bonus_permille_u64) * token_amount)
+        .unwrap_or_default();
+    // Counts the locked and vesting amounts, not minted yet
+    let supply_info = query_supply_info(&deps.querier, product)?;
+    ensure!(
+        token_amount + bonus <= supply_info.remaining,
+        ContractError::MaxSupplyReached {
+            denom: product.denom.clone(),
+            amount: token_amount + bonus,
+            remaining: supply_info.remaining,
+        }
+    );
+    Ok(SwapSimulation {
+        amount_out: token_amount + bonus,
+        bonus,
+        remaining: supply_info.remaining - token_amount - bonus,
+        unlocks_at: lock_duration
+            .map(|lock_duration| checked_plus_seconds(env.block.time, lock_duration))
+            .transpose()?,
+    })
+}
+
+// Reserve against the accepted denom paid if the whole default product supply was redeemed
+pub fn query_redemption_status(
+    deps: Deps<TokenFactoryQuery>,
+    config: &Config,
+) -> StdResult<RedemptionStatus> {
+    let redemption = REDEMPTION
+        .may_load(deps.storage)?
+        .unwrap_or_else(Redemption::disabled);
+    let reserve = RESERVE.may_load(deps.storage)?.unwrap_or_default();
+    // The redeemed tokens held by the contract aren't redeemed again
+    let supply = deps.querier.query_supply(&config.factory_denom)?.amount
+        - REDEEMED_HELD.may_load(deps.storage)?.unwrap_or_default();
+    let required = supply * redemption.rate;
+    Ok(RedemptionStatus {
+        redemption,
+        reserve,
+        required,
+        sufficient: reserve >= required,
+    })
+}
+
+// The bank supply may drift from the recorded amount through other mint paths or migrations
+pub fn query_supply_info(
+    querier: &QuerierWrapper<TokenFactoryQuery>,
+    product: &Product,
+) -> StdResult<SupplyInfo> {
+    let bank_supply = querier.query_supply(&product.denom)?.amount;
+    Ok(SupplyInfo {
+        denom: product.denom.clone(),
+        recorded: product.minted - product.unminted() - product.burned,
+        bank_supply,
+        cap: product.cap,
+        remaining: product.cap.saturating_sub(
+            product
+                .minted
+                .max(bank_supply + product.unminted() + product.burned),
+        ),
+    })
+}
+
+// Sends the coin with a reply, the amount is kept as a claim of the recipient if the send fails.
+// Nothing is sent for a zero amount, the bank rejects it
+pub fn payout_submsg(
+    storage: &mut dyn Storage,
+    recipient: &Addr,
+    amount: Coin,
+) -> StdResult<Option<SubMsg<TokenFactoryMsg>>> {
+    if amount.amount.is_zero() {
+        return Ok(None);
+    }
+    let id = NEXT_PAYOUT_ID
+        .may_load(storage)?
+        .unwrap_or(PAYOUT_REPLY_ID_START);
+    NEXT_PAYOUT_ID.save(storage, &(id + 1))?;
+
+    PENDING_PAYOUTS.save(
+        storage,
+        id,
+        &Payout {
+            recipient: recipient.clone(),
+            amount: amount.clone(),
+        },
+    )?;
+
+    Ok(Some(SubMsg::reply_always(
+        BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![amount],
+        },
+        id,
+    )))
+}
+
+// Sends the product over IBC with a reply, refunded to `refund_to` if the transfer fails.
+// A stargate `MsgTransfer` as ibc-hooks only calls back with a memo, which `IbcMsg::Transfer` lacks
+pub fn ibc_forward_submsg(
+    storage: &mut dyn Storage,
+    env: &Env,
+    refund_to: &Addr,
+    forward_ibc: IbcForward,
+    amount: Coin,
+) -> StdResult<SubMsg<TokenFactoryMsg>> {
+    PENDING_IBC_FORWARD.save(
+        storage,
+        &PendingIbcForward {
+            refund_to: refund_to.clone(),
+            channel: forward_ibc.channel.clone(),
+            amount: amount.clone(),
+        },
+    )?;
+
+    let timeout = checked_plus_seconds(env.block.time, forward_ibc.timeout)?;
+    // The acknowledgement or timeout is sent to `sudo`
+    let memo = format!(r#"{{"ibc_callback":"{}"}}"#, env.contract.address);
+    Ok(SubMsg::reply_always(
+        CosmosMsg::Stargate {
+            type_url: MSG_TRANSFER_TYPE_URL.to_string(),
+            value: encode_msg_transfer(
+                &env.contract.address,
+                &forward_ibc,
+                &amount,
+                timeout,
+                &memo,
+            )
+            .into(),
+        },
+        IBC_FORWARD_REPLY_ID,
+    ))
+}
+
+// `ibc.applications.transfer.v1.MsgTransfer`, with the timeout timestamp only
+pub fn encode_msg_transfer(
+    sender: &Addr,
+    forward_ibc: &IbcForward,
+    amount: &Coin,
+    timeout: Timestamp,
+    memo: &str,
+) -> Vec<u8> {
+    let mut token = vec![];
+    encode_bytes(&mut token, 1, amount.denom.as_bytes());
+    encode_bytes(&mut token, 2, amount.amount.to_string().as_bytes());
+
+    let mut msg = vec![];
+    encode_bytes(&mut msg, 1, TRANSFER_PORT.as_bytes());
+    encode_bytes(&mut msg, 2, forward_ibc.channel.as_bytes());
+    encode_bytes(&mut msg, 3, &token);
+    encode_bytes(&mut msg, 4, sender.as_str().as_bytes());
+    encode_bytes(&mut msg, 5, forward_ibc.receiver.as_bytes());
+    encode_uint64(&mut msg, 7, timeout.nanos());
+    encode_bytes(&mut msg, 8, memo.as_bytes());
+    msg
+}
+
+// Protobuf fields, left out when empty or zero as the default values
+fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn encode_uint64(buf: &mut Vec<u8>, field: u64, value: u64) {
+    if value == 0 {
+        return;
+    }
+    encode_varint(buf, field << 3);
+    encode_varint(buf, value);
+}
+
+fn encode_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    encode_varint(buf, field << 3 | 2);
+    encode_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+// Sends the burned share to the burn destination, `None` when it's kept in `TO_BURN`
+pub fn route_burn(
+    storage: &mut dyn Storage,
+    stats: &mut Statistics,
+    burned: Coin,
+) -> Result<Option<CosmosMsg<TokenFactoryMsg>>, ContractError> {
+    if burned.amount.is_zero() {
+        return Ok(None);
+    }
+
+    let burn_destination = BURN_DESTINATION
+        .may_load(storage)?
+        .unwrap_or(BurnDestination::Hold);
+    stats.add_burn(&burn_destination, burned.amount);
+
+    let msg = match burn_destination {
+        BurnDestination::Hold => {
+            TO_BURN.update(storage, |mut to_burn| -> StdResult<_> {
+                to_burn.amount += burned.amount;
+                Ok(to_burn)
+            })?;
+            None
+        }
+        BurnDestination::CommunityPool => Some(
+            DistributionMsg::FundCommunityPool {
+                amount: vec![burned],
+            }
+            .into(),
+        ),
+        BurnDestination::Contract { address, msg } => Some(
+            WasmMsg::Execute {
+                contract_addr: address,
+                msg,
+                funds: vec![burned],
+            }
+            .into(),
+        ),
+    };
+    Ok(msg)
+}
+
+pub fn cw20_execute_msg(
+    token: &Addr,
+    msg: &Cw20ExecuteMsg,
+) -> StdResult<CosmosMsg<TokenFactoryMsg>> {
+    Ok(WasmMsg::Execute {
+        contract_addr: token.to_string(),
+        msg: to_binary(msg)?,
+        funds: vec![],
+    }
+    .into())
+}
+
+// Address ibc-hooks sends the funds of a wasm memo from, as derived by the module:
+// sha256(sha256(prefix) | "channel/original_sender") in bech32
+pub fn derive_intermediate_sender(
+    channel: &str,
+    original_sender: &str,
+    bech32_prefix: &str,
+) -> StdResult<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(Sha256::digest(IBC_HOOKS_SENDER_PREFIX.as_bytes()));
+    hasher.update(format!("{}/{}", channel, original_sender).as_bytes());
+    bech32::encode(
+        bech32_prefix,
+        hasher.finalize().to_base32(),
+        Variant::Bech32,
+    )
+    .map_err(|error| StdError::generic_err(error.to_string()))
+}
+
+// The sequence from the `MsgTransferResponse` data of a `MsgTransfer`, field 1 as a varint
+pub fn transfer_sequence(data: &[u8]) -> Option<u64> {
+    let (tag, varint) = data.split_first()?;
+    if *tag != 0x08 {
+        return None;
+    }
+    let mut sequence = 0u64;
+    for (i, byte) in varint.iter().take(10).enumerate() {
+        sequence |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some(sequence);
+        }
+    }
+    None
+}
+
+// What the contract holds above its liabilities: the Juno to burn and the claims
+pub fn recoverable_amount(
+    storage: &dyn Storage,
+    config: &Config,
+    balance: &Coin,
+) -> StdResult<Uint128> {
+    if balance.denom == config.factory_denom {
+        let redeemed_held = REDEEMED_HELD.may_load(storage)?.unwrap_or_default();
+        return Ok(balance.amount.saturating_sub(redeemed_held));
+    }
+    if balance.denom != config.accepted_denom {
+        return Ok(balance.amount);
+    }
+    let to_burn = TO_BURN.load(storage)?;
+    let claims_total = CLAIMS_TOTAL.may_load(storage)?.unwrap_or_default();
+    let reserve = RESERVE.may_load(storage)?.unwrap_or_default();
+    Ok(balance
+        .amount
+        .saturating_sub(to_burn.amount)
+        .saturating_sub(claims_total)
+        .saturating_sub(reserve))
+}
+
+// Every invariant on the holdings, supplies and statistics which doesn't hold
+pub fn check_invariants(
+    deps: Deps<TokenFactoryQuery>,
+    env: &Env,
+) -> StdResult<Vec<InvariantViolation>> {
+    let config = CONFIG.load(deps.storage)?;
+    let stats = STATS.load(deps.storage)?;
+    let mut violations = vec![];
+    let mut violation = |invariant: &str, expected: String, actual: String| {
+        violations.push(InvariantViolation {
+            invariant: invariant.to_string(),
+            expected,
+            actual,
+        })
+    };
+
+    // The contract holds the Juno to burn, the claims and the redemption reserve
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &config.accepted_denom)?;
+    let liabilities = TO_BURN.load(deps.storage)?.amount
+        + CLAIMS_TOTAL.may_load(deps.storage)?.unwrap_or_default()
+        + RESERVE.may_load(deps.storage)?.unwrap_or_default();
+    if balance.amount < liabilities {
+        violation(
+            "holdings_cover_liabilities",
+            format!(">= {}", liabilities),
+            balance.amount.to_string(),
+        );
+    }
+
+    if stats.received > JUNO_MAX_SUPPLY {
+        violation(
+            "received_within_max_supply",
+            format!("<= {}", JUNO_MAX_SUPPLY),
+            stats.received.to_string(),
+        );
+    }
+    let shares = stats.burned
+        + stats.dev_fees
+        + stats.balance_dev_fund
+        + stats.juno_dev_fund
+        + stats.referral_fees;
+    if shares != stats.received {
+        violation(
+            "received_fully_distributed",
+            stats.received.to_string(),
+            shares.to_string(),
+        );
+    }
+
+    // Contracts instantiated before the registry only have the default product
+    let mut products = PRODUCTS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, product)| product))
+        .collect::<StdResult<Vec<Product>>>()?;
+    if products.is_empty() {
+        products.push(legacy_default_product(deps.storage, &config)?);
+    }
+    let mut minted = Uint128::zero();
+    for product in products.iter() {
+        minted += product.minted;
+        let bank_supply = deps.querier.query_supply(&product.denom)?.amount;
+        if bank_supply > product.cap {
+            violation(
+                &format!("supply_within_cap:{}", product.denom),
+                format!("<= {}", product.cap),
+                bank_supply.to_string(),
+            );
+        }
+        // The vesting positions and locks are minted later
+        let minted_supply = product
+            .minted
+            .saturating_sub(product.unminted() + product.burned);
+        if bank_supply > minted_supply {
+            violation(
+                &format!("supply_within_minted:{}", product.denom),
+                format!("<= {}", minted_supply),
+                bank_supply.to_string(),
+            );
+        }
+    }
+    if minted != stats.distributed {
+        violation(
+            "distributed_matches_products",
+            stats.distributed.to_string(),
+            minted.to_string(),
+        );
+    }
+
+    if let Some(snapshot) = BURNED_REMINTED_SNAPSHOT.may_load(deps.storage)? {
+        if snapshot.amount > stats.burned {
+            violation(
+                "snapshot_within_burned",
+                format!("<= {}", stats.burned),
+                snapshot.amount.to_string(),
+            );
+        }
+        let settled = SNAPSHOT_SETTLEMENT
+            .may_load(deps.storage)?
+            .map(|settlement| settlement.settled)
+            .unwrap_or_default();
+        if settled > snapshot.amount {
+            violation(
+                "snapshot_settled_within_snapshot",
+                format!("<= {}", snapshot.amount),
+                settled.to_string(),
+            );
+        }
+    }
+
+    Ok(violations)
+}
@@ -3,6 +3,7 @@ pub mod contract;
 pub mod error;
 pub mod executes;
 pub mod helpers;
+pub mod migrations;
 pub mod msg;
 pub mod state;
 mod tests;
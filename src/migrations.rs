@@ -0,0 +1,100 @@
+use cosmwasm_std::{Addr, Coin, DepsMut, Env, Uint128};
+use semver::Version;
+use token_bindings::TokenFactoryQuery;
+
+use crate::error::ContractError;
+use crate::helpers::load_product;
+use crate::state::{BurnedSnapshot, BURNED_REMINTED_SNAPSHOT, CONFIG, PRODUCTS, STATS, TO_BURN};
+
+type MigrationStep = fn(DepsMut<TokenFactoryQuery>, &Env) -> Result<(), ContractError>;
+
+// Ordered by version, each step brings the state to its version
+const MIGRATION_STEPS: [(&str, MigrationStep); 2] =
+    [("0.1.1", migrate_0_1_1), ("0.2.0", migrate_0_2_0)];
+
+pub fn parse_version(version: &str) -> Result<Version, ContractError> {
+    Version::parse(version).map_err(|_| ContractError::InvalidVersion {
+        version: version.to_string(),
+    })
+}
+
+// Runs the steps above `from` up to `to`, returns the versions of the steps which ran
+pub fn run_migrations(
+    mut deps: DepsMut<TokenFactoryQuery>,
+    env: &Env,
+    from: &Version,
+    to: &Version,
+) -> Result<Vec<String>, ContractError> {
+    let mut steps_ran = vec![];
+    for (version, step) in MIGRATION_STEPS {
+        let step_version = parse_version(version)?;
+        if from < &step_version && &step_version <= to {
+            step(deps.branch(), env)?;
+            steps_ran.push(version.to_string());
+        }
+    }
+    Ok(steps_ran)
+}
+
+fn migrate_0_1_1(deps: DepsMut<TokenFactoryQuery>, env: &Env) -> Result<(), ContractError> {
+    // Already applied - the 0.1.1 migrate didn't store its version, the contract still says 0.1.0
+    if BURNED_REMINTED_SNAPSHOT.may_load(deps.storage)?.is_some() {
+        return Ok(());
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let stats = STATS.load(deps.storage)?;
+
+    // Starts from 0 for the new burn module
+    TO_BURN.save(
+        deps.storage,
+        &Coin {
+            denom: config.accepted_denom.clone(),
+            amount: Uint128::zero(),
+        },
+    )?;
+
+    // This was burned but will be reminted - need to check if Juno can burn it when the new module
+    // happens
+    BURNED_REMINTED_SNAPSHOT.save(
+        deps.storage,
+        &BurnedSnapshot {
+            denom: config.accepted_denom,
+            amount: stats.burned,
+            snapshot_time: env.block.time,
+        },
+    )?;
+
+    Ok(())
+}
+
+fn migrate_0_2_0(deps: DepsMut<TokenFactoryQuery>, _env: &Env) -> Result<(), ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    // The addresses were stored as strings, loaded as `Addr` without any check
+    let validate = |field: &str, address: &Addr| {
+        deps.api
+            .addr_validate(address.as_str())
+            .map_err(|_| ContractError::InvalidStoredAddress {
+                field: field.to_string(),
+                address: address.to_string(),
+            })
+    };
+    config.contract_owner = validate("contract_owner", &config.contract_owner)?;
+    config.balance_development_fund_addr = validate(
+        "balance_development_fund_addr",
+        &config.balance_development_fund_addr,
+    )?;
+    config.juno_development_fund_addr = validate(
+        "juno_development_fund_addr",
+        &config.juno_development_fund_addr,
+    )?;
+    config.dev_addr = validate("dev_addr", &config.dev_addr)?;
+    CONFIG.save(deps.storage, &config)?;
+
+    // Registers the default product from the stats
+    let product = load_product(deps.storage, &config, None)?;
+    PRODUCTS.save(deps.storage, &product.denom, &product)?;
+
+    Ok(())
+}
@@ -1,8 +1,17 @@
 use cosmwasm_schema::cw_serde;
 
+use cosmwasm_std::{Decimal, Uint128};
+use cw20::Cw20ReceiveMsg;
+
+use crate::state::{
+    BurnCrank, BurnDestination, CircuitBreaker, DenomMetadata, IbcForward, Lockup, Operation,
+    Redemption, Referral, Role, SwapLimits, Vesting,
+};
+
 #[cw_serde]
 pub struct InstantiateMsg {
     pub accepted_denom: String,
+    pub subdenom: String,
     pub balance_development_fund_addr: String,
     pub juno_development_fund_addr: String,
     pub dev_addr: String,
@@ -10,13 +19,130 @@ pub struct InstantiateMsg {
     pub dev_fees_permille_u64: u64,
     pub balance_development_fund_permille_u64: u64,
     pub juno_development_fund_permille_u64: u64,
+    pub swap_limits: Option<SwapLimits>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+    pub denom_metadata: Option<DenomMetadata>,
+    pub burn_destination: Option<BurnDestination>,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
-    Swap {},
+    /// Swaps for the given product denom, the default one if `None`, with an optional referrer
+    /// getting the referral share. With a `lock_duration` in seconds, the tokens and the lock
+    /// bonus are released with `Withdraw` once unlocked. The product goes to `recipient`, the
+    /// sender if `None`, or is sent over IBC with `forward_ibc` and refunded to the recipient if
+    /// the transfer fails, `recipient` being required with `forward_ibc`. Swaps from ibc-hooks
+    /// should set `recipient`, their sender is the intermediate address returned by
+    /// `GetIntermediateSender`. The swap limits and vesting tiers apply to the recipient
+    Swap {
+        product: Option<String>,
+        referrer: Option<String>,
+        lock_duration: Option<u64>,
+        recipient: Option<String>,
+        forward_ibc: Option<IbcForward>,
+    },
+    /// Deprecated: toggles swapping, use `Pause` / `Unpause` instead
     EnableDisable {},
     Burn {},
+    UpdateSwapLimits {
+        swap_limits: SwapLimits,
+    },
+    UpdateCircuitBreaker {
+        circuit_breaker: Option<CircuitBreaker>,
+    },
+    ResetCircuitBreaker {},
+    GrantRole {
+        role: Role,
+        address: String,
+    },
+    RevokeRole {
+        role: Role,
+        address: String,
+    },
+    /// Pauses the given operations, all of them if `None`
+    Pause {
+        operations: Option<Vec<Operation>>,
+        reason: Option<String>,
+    },
+    /// Unpauses the given operations, all of them if `None`
+    Unpause {
+        operations: Option<Vec<Operation>>,
+    },
+    SetDenomMetadata {
+        metadata: DenomMetadata,
+    },
+    CreateProduct {
+        subdenom: String,
+        rate: Decimal,
+        cap: Uint128,
+        metadata: Option<DenomMetadata>,
+    },
+    /// Hands the token factory admin of the product, the default one if `None`, to `new_admin`
+    ChangeDenomAdmin {
+        denom: Option<String>,
+        new_admin: String,
+    },
+    /// Sweeps the balances and renounces minting once the default product is sold out
+    Finalize {},
+    UpdateBurnDestination {
+        burn_destination: BurnDestination,
+    },
+    UpdateBurnCrank {
+        burn_crank: BurnCrank,
+    },
+    UpdateReferral {
+        referral: Referral,
+    },
+    UpdateVesting {
+        vesting: Vesting,
+    },
+    /// Mints the unlocked part of the vesting positions of the sender
+    ClaimVested {},
+    UpdateLockup {
+        lockup: Lockup,
+    },
+    /// Mints the unlocked locks of the sender
+    Withdraw {},
+    UpdateRedemption {
+        redemption: Redemption,
+    },
+    /// Adds the accepted denom sent to the redemption reserve
+    FundReserve {},
+    /// Sends the reserve back, e.g. once the redemption window is over
+    WithdrawReserve {
+        amount: Uint128,
+        to: String,
+    },
+    /// Burns the default product sent for the accepted denom at the redemption rate, holds it once
+    /// the denom admin is handed over
+    Redeem {},
+    /// Swaps the tokens of an allowlisted CW20 sent with `Cw20ExecuteMsg::Send`
+    Receive(Cw20ReceiveMsg),
+    UpdateCw20Allowlist {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    /// Burns the Juno to burn once the burn crank thresholds are reached, for a tip
+    CrankBurn {},
+    /// Burns the funds sent against what is left of the burned snapshot
+    SettleSnapshotDebt {},
+    /// Allows `SettleSnapshotDebt` once the chain can burn the funds
+    UpdateSnapshotSettlement {
+        enabled: bool,
+    },
+    /// Sends tokens held above the liabilities of the contract, e.g. sent to it by mistake
+    RecoverFunds {
+        denom: String,
+        amount: Uint128,
+        to: String,
+    },
+    /// Withdraws the payouts which failed to be sent to the sender
+    Claim {},
+    /// Burns the product, the default one if `None`, sent to the contract by its holders
+    BurnFrom {
+        denom: Option<String>,
+        amount: Uint128,
+    },
 }
 
 #[cw_serde]
@@ -25,9 +151,121 @@ pub enum QueryMsg {
     GetStats {},
     GetBurnedSnapshot {},
     GetToBurn {},
+    GetSwapLimits {},
+    GetSwapAllowance {
+        address: String,
+    },
+    GetCircuitBreaker {},
+    GetRoleMembers {
+        role: Role,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    GetRoles {
+        address: String,
+    },
+    GetPauseStatus {},
+    GetDenomMetadata {},
+    GetProduct {
+        denom: Option<String>,
+    },
+    ListProducts {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    GetDenomAdmin {
+        denom: Option<String>,
+    },
+    GetLifecycle {},
+    GetRecoverable {},
+    CheckInvariants {},
+    GetBurnDestination {},
+    GetBurnCrank {},
+    GetSnapshotDebt {},
+    GetBurnHistory {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    GetClaim {
+        address: String,
+    },
+    GetSupplyInfo {
+        denom: Option<String>,
+    },
+    GetReferral {},
+    GetReferrer {
+        address: String,
+    },
+    /// Referrers by the amount referred, the highest first
+    ListTopReferrers {
+        limit: Option<u32>,
+    },
+    GetVesting {},
+    ListVestingPositions {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Unlocked and not claimed yet, per denom
+    GetClaimableVested {
+        address: String,
+    },
+    GetLockup {},
+    ListLocks {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Swap of `amount` of the accepted denom for the given product, the default one if `None`
+    SimulateSwap {
+        amount: Uint128,
+        product: Option<String>,
+        lock_duration: Option<u64>,
+    },
+    GetRedemption {},
+    GetCw20Allowlist {},
+    GetCw20Stats {
+        token: String,
+    },
+    /// Sender of the swaps made with an ibc-hooks memo by `original_sender` over the Juno side
+    /// `channel`
+    GetIntermediateSender {
+        channel: String,
+        original_sender: String,
+    },
+}
+
+/// Sent with `Cw20ExecuteMsg::Send`. The received amount counts 1:1 as an amount of the accepted
+/// denom towards the swap limits, circuit breaker and vesting tiers, shared with the native swaps
+#[cw_serde]
+pub enum ReceiveMsg {
+    /// Swaps for the given product denom, the default one if `None`
+    Swap { product: Option<String> },
+}
+
+/// Called by ibc-hooks with the outcome of the `forward_ibc` transfers
+#[cw_serde]
+pub enum SudoMsg {
+    IbcLifecycleComplete(IbcLifecycleComplete),
+}
+
+#[cw_serde]
+pub enum IbcLifecycleComplete {
+    IbcAck {
+        channel: String,
+        sequence: u64,
+        ack: String,
+        success: bool,
+    },
+    IbcTimeout {
+        channel: String,
+        sequence: u64,
+    },
 }
 
 #[cw_serde]
 pub struct MigrateMsg {
-    pub states_update: bool
+    /// Deprecated: the migration steps run from the stored contract version
+    #[serde(default)]
+    pub states_update: bool,
 }
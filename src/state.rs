@@ -1,21 +1,25 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{ensure, Coin, DepsMut, Timestamp, Uint128};
-use cw_storage_plus::Item;
-use token_bindings::TokenFactoryQuery;
+use cosmwasm_std::{
+    ensure, Addr, Api, Binary, BlockInfo, Coin, Decimal, DepsMut, Empty, Timestamp, Uint128,
+};
+use cw_storage_plus::{Item, Map};
+use cw_utils::Duration;
+use token_bindings::{DenomUnit, Metadata, TokenFactoryQuery};
 
+use crate::constants::{MAX_DURATION, WINDOW_BUCKETS};
 use crate::error::ContractError;
 use crate::helpers::{compute_amounts_to_distribute, AmountsToDistribute};
 use crate::msg::InstantiateMsg;
 
 #[cw_serde]
 pub struct Config {
-    pub contract_owner: String,
+    pub contract_owner: Addr,
     pub enabled: bool,
     pub accepted_denom: String,
     pub factory_denom: String,
-    pub balance_development_fund_addr: String,
-    pub juno_development_fund_addr: String,
-    pub dev_addr: String,
+    pub balance_development_fund_addr: Addr,
+    pub juno_development_fund_addr: Addr,
+    pub dev_addr: Addr,
     pub burn_permille_u64: u64,
     pub dev_fees_permille_u64: u64,
     pub balance_development_fund_permille_u64: u64,
@@ -26,7 +30,7 @@ impl Config {
     pub fn validate(
         sender: &str,
         deps: DepsMut<TokenFactoryQuery>,
-        mut init_msg: InstantiateMsg,
+        init_msg: InstantiateMsg,
         factory_denom: String,
     ) -> Result<Self, ContractError> {
         let total_dist = init_msg.burn_permille_u64
@@ -48,24 +52,18 @@ impl Config {
         validate_range(init_msg.juno_development_fund_permille_u64)?;
         validate_range(init_msg.balance_development_fund_permille_u64)?;
 
-        init_msg.balance_development_fund_addr = deps
-            .api
-            .addr_validate(&init_msg.balance_development_fund_addr)?
-            .to_string();
-        init_msg.juno_development_fund_addr = deps
-            .api
-            .addr_validate(&init_msg.juno_development_fund_addr)?
-            .to_string();
-        init_msg.dev_addr = deps.api.addr_validate(&init_msg.dev_addr)?.to_string();
-
         let config = Config {
-            contract_owner: deps.api.addr_validate(sender)?.to_string(),
+            contract_owner: deps.api.addr_validate(sender)?,
             enabled: true,
             accepted_denom: init_msg.accepted_denom,
             factory_denom,
-            balance_development_fund_addr: init_msg.balance_development_fund_addr,
-            juno_development_fund_addr: init_msg.juno_development_fund_addr,
-            dev_addr: init_msg.dev_addr,
+            balance_development_fund_addr: deps
+                .api
+                .addr_validate(&init_msg.balance_development_fund_addr)?,
+            juno_development_fund_addr: deps
+                .api
+                .addr_validate(&init_msg.juno_development_fund_addr)?,
+            dev_addr: deps.api.addr_validate(&init_msg.dev_addr)?,
             burn_permille_u64: init_msg.burn_permille_u64,
             dev_fees_permille_u64: init_msg.dev_fees_permille_u64,
             balance_development_fund_permille_u64: init_msg.balance_development_fund_permille_u64,
@@ -89,6 +87,22 @@ pub struct Statistics {
     pub dev_fees: Uint128,
     pub balance_dev_fund: Uint128,
     pub juno_dev_fund: Uint128,
+    // Where the burned share went, since the burn destination is configurable
+    #[serde(default)]
+    pub burn_held: Uint128,
+    #[serde(default)]
+    pub burn_community_pool: Uint128,
+    #[serde(default)]
+    pub burn_contract: Uint128,
+    // Share of the dev fees paid to referrers
+    #[serde(default)]
+    pub referral_fees: Uint128,
+    // Default product sent back with `Redeem` and burned
+    #[serde(default)]
+    pub redeemed: Uint128,
+    // Accepted denom paid out of the reserve for the redemptions
+    #[serde(default)]
+    pub redemption_payouts: Uint128,
 }
 
 impl Statistics {
@@ -100,6 +114,12 @@ impl Statistics {
             dev_fees: Uint128::zero(),
             balance_dev_fund: Uint128::zero(),
             juno_dev_fund: Uint128::zero(),
+            burn_held: Uint128::zero(),
+            burn_community_pool: Uint128::zero(),
+            burn_contract: Uint128::zero(),
+            referral_fees: Uint128::zero(),
+            redeemed: Uint128::zero(),
+            redemption_payouts: Uint128::zero(),
         }
     }
 
@@ -113,12 +133,41 @@ impl Statistics {
         self.received += swap_amount_in;
         self.distributed += swap_amount_out;
         self.dev_fees += amount_to_distribute.dev;
+        self.referral_fees += amount_to_distribute.referral;
         self.balance_dev_fund += amount_to_distribute.balance_dev_fund;
         self.juno_dev_fund += amount_to_distribute.juno_dev_fund;
         self
     }
+
+    pub fn add_burn(&mut self, burn_destination: &BurnDestination, amount: Uint128) {
+        match burn_destination {
+            BurnDestination::Hold => self.burn_held += amount,
+            BurnDestination::CommunityPool => self.burn_community_pool += amount,
+            BurnDestination::Contract { .. } => self.burn_contract += amount,
+        }
+    }
+}
+
+#[cw_serde]
+pub enum BurnDestination {
+    // Kept in `TO_BURN` for a later `Burn`
+    Hold,
+    CommunityPool,
+    // Executes `msg` on the contract with the burned share attached
+    Contract { address: String, msg: Binary },
+}
+
+impl BurnDestination {
+    pub fn validate(self, api: &dyn Api) -> Result<Self, ContractError> {
+        if let BurnDestination::Contract { address, .. } = &self {
+            api.addr_validate(address)?;
+        }
+        Ok(self)
+    }
 }
 
+pub const BURN_DESTINATION: Item<BurnDestination> = Item::new("burn_destination");
+
 pub const STATS: Item<Statistics> = Item::new("stats");
 
 // Only used as a point in time which was using a dead address to burn
@@ -130,4 +179,886 @@ pub struct BurnedSnapshot {
 }
 pub const BURNED_REMINTED_SNAPSHOT: Item<BurnedSnapshot> = Item::new("burned_reminted_snapshot");
 
+// Part of the snapshot burned again with `SettleSnapshotDebt`, once enabled by the owner
+#[cw_serde]
+pub struct SnapshotSettlement {
+    #[serde(default)]
+    pub enabled: bool,
+    pub settled: Uint128,
+    pub last_settled_at: Option<Timestamp>,
+}
+
+impl SnapshotSettlement {
+    pub fn zero() -> Self {
+        SnapshotSettlement {
+            enabled: false,
+            settled: Uint128::zero(),
+            last_settled_at: None,
+        }
+    }
+}
+
+pub const SNAPSHOT_SETTLEMENT: Item<SnapshotSettlement> = Item::new("snapshot_settlement");
+
+#[cw_serde]
+pub struct SnapshotDebt {
+    pub enabled: bool,
+    pub denom: String,
+    pub snapshot: Uint128,
+    pub settled: Uint128,
+    pub remaining: Uint128,
+    pub last_settled_at: Option<Timestamp>,
+}
+
 pub const TO_BURN: Item<Coin> = Item::new("to_burn");
+
+#[cw_serde]
+pub struct WindowLimit {
+    pub window: Duration,
+    pub max: Uint128,
+}
+
+// Limits on the input amount of a swap - `None` means no limit
+#[cw_serde]
+pub struct SwapLimits {
+    pub per_tx_max: Option<Uint128>,
+    pub per_address_max: Option<Uint128>,
+    pub per_address_window: Option<WindowLimit>,
+}
+
+impl SwapLimits {
+    pub fn unlimited() -> Self {
+        SwapLimits {
+            per_tx_max: None,
+            per_address_max: None,
+            per_address_window: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ContractError> {
+        let non_zero = |value: &Option<Uint128>| -> Result<(), ContractError> {
+            match value {
+                Some(max) if max.is_zero() => Err(ContractError::InvalidSwapLimits {}),
+                _ => Ok(()),
+            }
+        };
+
+        non_zero(&self.per_tx_max)?;
+        non_zero(&self.per_address_max)?;
+
+        if let Some(window_limit) = &self.per_address_window {
+            ensure!(
+                !window_limit.max.is_zero()
+                    && !matches!(window_limit.window, Duration::Height(0) | Duration::Time(0)),
+                ContractError::InvalidSwapLimits {}
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn allowance(&self, swapped: &AddressSwapped, block: &BlockInfo) -> SwapAllowance {
+        SwapAllowance {
+            per_tx: self.per_tx_max,
+            per_address: self
+                .per_address_max
+                .map(|max| max.saturating_sub(swapped.total)),
+            per_window: self.per_address_window.as_ref().map(|window_limit| {
+                window_limit
+                    .max
+                    .saturating_sub(swapped.window.amount(&window_limit.window, block))
+            }),
+        }
+    }
+
+    // Checks the amount against every limit and records it for the address
+    pub fn check_and_record(
+        &self,
+        swapped: &mut AddressSwapped,
+        amount: Uint128,
+        block: &BlockInfo,
+    ) -> Result<(), ContractError> {
+        let allowance = self.allowance(swapped, block);
+
+        if let Some(remaining) = allowance.per_tx {
+            ensure!(
+                amount <= remaining,
+                ContractError::SwapLimitPerTxExceeded { remaining }
+            );
+        }
+        if let Some(remaining) = allowance.per_address {
+            ensure!(
+                amount <= remaining,
+                ContractError::SwapLimitPerAddressExceeded { remaining }
+            );
+        }
+        if let Some(remaining) = allowance.per_window {
+            ensure!(
+                amount <= remaining,
+                ContractError::SwapLimitPerWindowExceeded { remaining }
+            );
+        }
+
+        if let Some(window_limit) = &self.per_address_window {
+            swapped.window.add(&window_limit.window, block, amount);
+        }
+        swapped.total += amount;
+
+        Ok(())
+    }
+}
+
+pub const SWAP_LIMITS: Item<SwapLimits> = Item::new("swap_limits");
+
+#[cw_serde]
+pub struct SwapAllowance {
+    pub per_tx: Option<Uint128>,
+    pub per_address: Option<Uint128>,
+    pub per_window: Option<Uint128>,
+}
+
+// Amounts swapped within a rolling window, in buckets of `WINDOW_BUCKETS` parts of the window.
+// Every bucket overlapping the window is counted, so the amount is never below the exact sum
+#[cw_serde]
+pub struct RollingAmount {
+    // Start of the bucket in blocks or seconds and the amount recorded in it, oldest first
+    pub buckets: Vec<(u64, Uint128)>,
+}
+
+impl RollingAmount {
+    pub fn zero() -> Self {
+        RollingAmount { buckets: vec![] }
+    }
+
+    // Position of the block and length of the window, with the size of its buckets
+    fn bounds(window: &Duration, block: &BlockInfo) -> (u64, u64, u64) {
+        let (now, length) = match window {
+            Duration::Height(height) => (block.height, *height),
+            Duration::Time(time) => (block.time.seconds(), *time),
+        };
+        (now, length, (length / WINDOW_BUCKETS).max(1))
+    }
+
+    pub fn amount(&self, window: &Duration, block: &BlockInfo) -> Uint128 {
+        let (now, length, bucket_size) = Self::bounds(window, block);
+        let window_start = now.saturating_sub(length);
+        self.buckets
+            .iter()
+            .filter(|(start, _)| start + bucket_size > window_start)
+            .map(|(_, amount)| *amount)
+            .sum()
+    }
+
+    pub fn add(&mut self, window: &Duration, block: &BlockInfo, amount: Uint128) {
+        let (now, length, bucket_size) = Self::bounds(window, block);
+        let window_start = now.saturating_sub(length);
+        self.buckets
+            .retain(|(start, _)| start + bucket_size > window_start);
+
+        let bucket_start = now - now % bucket_size;
+        match self.buckets.last_mut() {
+            Some((start, bucket_amount)) if *start == bucket_start => *bucket_amount += amount,
+            _ => self.buckets.push((bucket_start, amount)),
+        }
+    }
+}
+
+#[cw_serde]
+pub struct AddressSwapped {
+    pub total: Uint128,
+    pub window: RollingAmount,
+}
+
+impl AddressSwapped {
+    pub fn zero() -> Self {
+        AddressSwapped {
+            total: Uint128::zero(),
+            window: RollingAmount::zero(),
+        }
+    }
+}
+
+pub const SWAPPED_BY_ADDRESS: Map<&Addr, AddressSwapped> = Map::new("swapped_by_address");
+
+// Pauses swapping once a swap would take the total swapped within the rolling window above
+// `max_volume`, until reset by the guardian or an owner
+#[cw_serde]
+pub struct CircuitBreaker {
+    pub window: Duration,
+    pub max_volume: Uint128,
+    pub guardian: Option<String>,
+}
+
+impl CircuitBreaker {
+    pub fn validate(mut self, api: &dyn Api) -> Result<Self, ContractError> {
+        ensure!(
+            !self.max_volume.is_zero()
+                && !matches!(self.window, Duration::Height(0) | Duration::Time(0)),
+            ContractError::InvalidCircuitBreaker {}
+        );
+        self.guardian = self
+            .guardian
+            .map(|guardian| api.addr_validate(&guardian).map(|addr| addr.to_string()))
+            .transpose()?;
+        Ok(self)
+    }
+
+    pub fn window_amount(&self, volume: &SwapVolume, block: &BlockInfo) -> Uint128 {
+        volume.window.amount(&self.window, block)
+    }
+
+    // Records an amount fitting under `max_volume`, or trips the breaker and returns true for an
+    // amount crossing it
+    pub fn record(&self, volume: &mut SwapVolume, amount: Uint128, block: &BlockInfo) -> bool {
+        if self.window_amount(volume, block) + amount > self.max_volume {
+            volume.tripped_at = Some(block.time);
+            return true;
+        }
+        volume.window.add(&self.window, block, amount);
+        false
+    }
+}
+
+pub const CIRCUIT_BREAKER: Item<CircuitBreaker> = Item::new("circuit_breaker");
+
+#[cw_serde]
+pub struct SwapVolume {
+    pub window: RollingAmount,
+    pub tripped_at: Option<Timestamp>,
+}
+
+impl SwapVolume {
+    pub fn zero() -> Self {
+        SwapVolume {
+            window: RollingAmount::zero(),
+            tripped_at: None,
+        }
+    }
+}
+
+pub const SWAP_VOLUME: Item<SwapVolume> = Item::new("swap_volume");
+
+#[cw_serde]
+pub struct CircuitBreakerStatus {
+    pub circuit_breaker: Option<CircuitBreaker>,
+    pub volume: SwapVolume,
+    // Swapped within the window ending at the query block
+    pub window_amount: Uint128,
+}
+
+// Owners hold every role, the `contract_owner` in the config is always an owner
+#[cw_serde]
+#[derive(Copy)]
+pub enum Role {
+    Owner,
+    Pauser,
+    ConfigManager,
+    Burner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Pauser => "pauser",
+            Role::ConfigManager => "config_manager",
+            Role::Burner => "burner",
+        }
+    }
+
+    pub fn all() -> Vec<Role> {
+        vec![Role::Owner, Role::Pauser, Role::ConfigManager, Role::Burner]
+    }
+}
+
+pub const ROLES: Map<(&str, &Addr), Empty> = Map::new("roles");
+
+#[cw_serde]
+pub struct RoleMembers {
+    pub role: Role,
+    pub members: Vec<String>,
+}
+
+#[cw_serde]
+#[derive(Copy)]
+pub enum Operation {
+    Swap,
+    Burn,
+    Claims,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Swap => "swap",
+            Operation::Burn => "burn",
+            Operation::Claims => "claims",
+        }
+    }
+
+    pub fn all() -> Vec<Operation> {
+        vec![Operation::Swap, Operation::Burn, Operation::Claims]
+    }
+}
+
+#[cw_serde]
+pub struct PauseInfo {
+    pub reason: Option<String>,
+    pub paused_at: Timestamp,
+    pub paused_by: String,
+}
+
+// Only paused operations are stored
+pub const PAUSED: Map<&str, PauseInfo> = Map::new("paused");
+
+#[cw_serde]
+pub struct OperationStatus {
+    pub operation: Operation,
+    pub paused: Option<PauseInfo>,
+}
+
+// Bank metadata of the factory denom - the `uri` is only kept by the contract as the token
+// factory bindings do not carry it
+#[cw_serde]
+pub struct DenomMetadata {
+    pub description: Option<String>,
+    pub denom_units: Vec<DenomUnit>,
+    pub display: String,
+    pub name: String,
+    pub symbol: String,
+    pub uri: Option<String>,
+}
+
+impl DenomMetadata {
+    // Adds the base unit if missing and checks the display unit exists
+    pub fn validate(mut self, base: &str) -> Result<Self, ContractError> {
+        if !self.denom_units.iter().any(|unit| unit.denom == base) {
+            self.denom_units.insert(
+                0,
+                DenomUnit {
+                    denom: base.to_string(),
+                    exponent: 0,
+                    aliases: vec![],
+                },
+            );
+        }
+
+        ensure!(
+            !self.name.is_empty()
+                && !self.symbol.is_empty()
+                && self
+                    .denom_units
+                    .iter()
+                    .any(|unit| unit.denom == self.display),
+            ContractError::InvalidDenomMetadata {}
+        );
+
+        Ok(self)
+    }
+
+    pub fn to_token_factory(&self, base: &str) -> Metadata {
+        Metadata {
+            description: self.description.clone(),
+            denom_units: self.denom_units.clone(),
+            base: Some(base.to_string()),
+            display: Some(self.display.clone()),
+            name: Some(self.name.clone()),
+            symbol: Some(self.symbol.clone()),
+        }
+    }
+}
+
+pub const DENOM_METADATA: Item<DenomMetadata> = Item::new("denom_metadata");
+
+// Token factory denom minted by the contract - the one in the config is the default product
+#[cw_serde]
+pub struct Product {
+    pub subdenom: String,
+    pub denom: String,
+    pub rate: Decimal,
+    pub cap: Uint128,
+    pub minted: Uint128,
+    // Token factory admin once handed over by the contract, nothing can be minted after that
+    #[serde(default)]
+    pub admin_transferred_to: Option<String>,
+    // Part of `minted` held in vesting positions, only minted when claimed
+    #[serde(default)]
+    pub vesting: Uint128,
+    // Part of `minted` held in locks, only minted when withdrawn
+    #[serde(default)]
+    pub locked: Uint128,
+    // Burned with `Redeem` or `BurnFrom`, still counted in `minted` so no room is freed under the cap
+    #[serde(default)]
+    pub burned: Uint128,
+}
+
+impl Product {
+    pub fn new(
+        contract: &Addr,
+        subdenom: String,
+        rate: Decimal,
+        cap: Uint128,
+    ) -> Result<Self, ContractError> {
+        ensure!(
+            !rate.is_zero() && !cap.is_zero(),
+            ContractError::InvalidProduct {}
+        );
+        Ok(Product {
+            denom: factory_denom(contract, &subdenom)?,
+            subdenom,
+            rate,
+            cap,
+            minted: Uint128::zero(),
+            admin_transferred_to: None,
+            vesting: Uint128::zero(),
+            locked: Uint128::zero(),
+            burned: Uint128::zero(),
+        })
+    }
+
+    // Counted in `minted` but not minted yet
+    pub fn unminted(&self) -> Uint128 {
+        self.vesting + self.locked
+    }
+}
+
+pub fn factory_denom(contract: &Addr, subdenom: &str) -> Result<String, ContractError> {
+    // Token factory limit on the subdenom length
+    ensure!(
+        !subdenom.is_empty()
+            && subdenom.len() <= 44
+            && subdenom
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_'),
+        ContractError::InvalidSubdenom {}
+    );
+    Ok(format!("factory/{}/{}", contract, subdenom))
+}
+
+// Keyed by the full denom
+pub const PRODUCTS: Map<&str, Product> = Map::new("products");
+
+#[cw_serde]
+pub struct SupplyInfo {
+    pub denom: String,
+    pub recorded: Uint128,
+    pub bank_supply: Uint128,
+    pub cap: Uint128,
+    // Left under the cap, from the greater of the recorded and the bank supply
+    pub remaining: Uint128,
+}
+
+impl SupplyInfo {
+    pub fn is_mismatch(&self) -> bool {
+        self.recorded != self.bank_supply
+    }
+}
+
+#[cw_serde]
+#[derive(Copy)]
+pub enum Phase {
+    Active,
+    Paused,
+    // The default product is sold out but `Finalize` wasn't sent yet
+    CapReached,
+    Finalized,
+}
+
+#[cw_serde]
+pub struct Lifecycle {
+    pub phase: Phase,
+    pub finalized_at: Option<Timestamp>,
+}
+
+pub const FINALIZED_AT: Item<Timestamp> = Item::new("finalized_at");
+
+// Denom expected from the `CreateDenom` waiting for its reply
+pub const PENDING_DENOM: Item<String> = Item::new("pending_denom");
+
+#[cw_serde]
+pub struct Payout {
+    pub recipient: Addr,
+    pub amount: Coin,
+}
+
+// Payouts waiting for their reply, keyed by reply id
+pub const PENDING_PAYOUTS: Map<u64, Payout> = Map::new("pending_payouts");
+pub const NEXT_PAYOUT_ID: Item<u64> = Item::new("next_payout_id");
+
+// Payouts which failed, claimable by their recipient with `Claim`
+pub const CLAIMS: Map<&Addr, Uint128> = Map::new("claims");
+pub const CLAIMS_TOTAL: Item<Uint128> = Item::new("claims_total");
+
+#[cw_serde]
+pub struct BurnCrank {
+    // Stays disabled until the new burn module is live on Juno
+    pub enabled: bool,
+    pub min_amount: Uint128,
+    // In seconds since the last burn
+    pub min_interval: u64,
+    // Tip sent to the caller of `CrankBurn`, in permille of the burned amount
+    pub tip_permille_u64: u64,
+}
+
+impl BurnCrank {
+    pub fn disabled() -> Self {
+        BurnCrank {
+            enabled: false,
+            min_amount: Uint128::zero(),
+            min_interval: 0,
+            tip_permille_u64: 0,
+        }
+    }
+
+    pub fn validate(self) -> Result<Self, ContractError> {
+        ensure!(
+            self.tip_permille_u64 <= 100 && self.min_interval <= MAX_DURATION,
+            ContractError::InvalidBurnCrank {}
+        );
+        Ok(self)
+    }
+
+    // Either enough to burn or long enough since the last burn
+    pub fn is_ready(&self, to_burn: Uint128, last_burn: Option<Timestamp>, now: Timestamp) -> bool {
+        let interval_reached = match last_burn {
+            Some(last_burn) => {
+                now.seconds() >= last_burn.seconds().saturating_add(self.min_interval)
+            }
+            None => true,
+        };
+        !to_burn.is_zero() && (to_burn >= self.min_amount || interval_reached)
+    }
+}
+
+pub const BURN_CRANK: Item<BurnCrank> = Item::new("burn_crank");
+
+#[cw_serde]
+pub struct BurnRecord {
+    pub id: u64,
+    pub amount: Coin,
+    pub tip: Uint128,
+    pub burned_by: String,
+    pub burned_at: Timestamp,
+}
+
+pub const BURN_HISTORY: Map<u64, BurnRecord> = Map::new("burn_history");
+
+#[cw_serde]
+pub struct InvariantViolation {
+    pub invariant: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[cw_serde]
+pub enum ReferralSource {
+    // Taken out of the dev fees, paid in the accepted denom
+    DevFees,
+    // Minted in addition to the swapped product, within its cap
+    Mint,
+}
+
+#[cw_serde]
+pub struct Referral {
+    pub enabled: bool,
+    pub source: ReferralSource,
+    // In permille of the dev fees or of the minted amount
+    pub share_permille_u64: u64,
+}
+
+impl Referral {
+    pub fn disabled() -> Self {
+        Referral {
+            enabled: false,
+            source: ReferralSource::DevFees,
+            share_permille_u64: 0,
+        }
+    }
+
+    pub fn validate(self) -> Result<Self, ContractError> {
+        ensure!(
+            self.share_permille_u64 <= 1000,
+            ContractError::InvalidReferral {}
+        );
+        Ok(self)
+    }
+}
+
+pub const REFERRAL: Item<Referral> = Item::new("referral");
+
+#[cw_serde]
+pub struct ReferrerStats {
+    pub referrer: Addr,
+    pub swaps: u64,
+    // Received from the referred swaps
+    pub referred: Uint128,
+    // Paid out of the dev fees
+    pub fees_earned: Uint128,
+    // Minted, across the products
+    pub minted_earned: Uint128,
+}
+
+impl ReferrerStats {
+    pub fn zero(referrer: Addr) -> Self {
+        ReferrerStats {
+            referrer,
+            swaps: 0,
+            referred: Uint128::zero(),
+            fees_earned: Uint128::zero(),
+            minted_earned: Uint128::zero(),
+        }
+    }
+}
+
+pub const REFERRERS: Map<&Addr, ReferrerStats> = Map::new("referrers");
+// Ordered by the amount referred, for `ListTopReferrers`
+pub const REFERRERS_BY_VOLUME: Map<(u128, &Addr), Empty> = Map::new("referrers_by_volume");
+
+#[cw_serde]
+pub struct VestingTier {
+    // Swaps bringing the total swapped by the address to at least this amount use the tier
+    pub min_amount: Uint128,
+    // In seconds, nothing unlocks before
+    pub cliff: u64,
+    // In seconds after the cliff, unlocking linearly
+    pub duration: u64,
+}
+
+#[cw_serde]
+pub struct Vesting {
+    pub enabled: bool,
+    // By ascending `min_amount`, swaps below the first tier are minted right away
+    pub tiers: Vec<VestingTier>,
+}
+
+impl Vesting {
+    pub fn disabled() -> Self {
+        Vesting {
+            enabled: false,
+            tiers: vec![],
+        }
+    }
+
+    pub fn validate(self) -> Result<Self, ContractError> {
+        ensure!(
+            self.tiers
+                .windows(2)
+                .all(|tiers| tiers[0].min_amount < tiers[1].min_amount)
+                && self
+                    .tiers
+                    .iter()
+                    .all(|tier| tier.cliff <= MAX_DURATION && tier.duration <= MAX_DURATION),
+            ContractError::InvalidVesting {}
+        );
+        Ok(self)
+    }
+
+    pub fn tier(&self, amount: Uint128) -> Option<&VestingTier> {
+        if !self.enabled {
+            return None;
+        }
+        self.tiers
+            .iter()
+            .rev()
+            .find(|tier| tier.min_amount <= amount)
+    }
+}
+
+pub const VESTING: Item<Vesting> = Item::new("vesting");
+
+#[cw_serde]
+pub struct VestingPosition {
+    pub id: u64,
+    pub owner: Addr,
+    pub denom: String,
+    pub amount: Uint128,
+    pub claimed: Uint128,
+    pub start: Timestamp,
+    pub cliff: u64,
+    pub duration: u64,
+}
+
+impl VestingPosition {
+    pub fn vested(&self, now: Timestamp) -> Uint128 {
+        let cliff_end = self.start.seconds().saturating_add(self.cliff);
+        if now.seconds() < cliff_end {
+            return Uint128::zero();
+        }
+        let elapsed = now.seconds() - cliff_end;
+        if elapsed >= self.duration {
+            return self.amount;
+        }
+        self.amount.multiply_ratio(elapsed, self.duration)
+    }
+
+    pub fn claimable(&self, now: Timestamp) -> Uint128 {
+        self.vested(now) - self.claimed
+    }
+}
+
+// Removed once fully claimed
+pub const VESTING_POSITIONS: Map<(&Addr, u64), VestingPosition> = Map::new("vesting_positions");
+pub const NEXT_VESTING_ID: Item<u64> = Item::new("next_vesting_id");
+
+#[cw_serde]
+pub struct LockBonus {
+    // In seconds
+    pub duration: u64,
+    // In permille of the swapped amount, minted on top
+    pub bonus_permille_u64: u64,
+}
+
+#[cw_serde]
+pub struct Lockup {
+    pub enabled: bool,
+    // By ascending `duration`
+    pub bonuses: Vec<LockBonus>,
+}
+
+impl Lockup {
+    pub fn disabled() -> Self {
+        Lockup {
+            enabled: false,
+            bonuses: vec![],
+        }
+    }
+
+    pub fn validate(self) -> Result<Self, ContractError> {
+        ensure!(
+            self.bonuses
+                .windows(2)
+                .all(|bonuses| bonuses[0].duration < bonuses[1].duration)
+                && self
+                    .bonuses
+                    .iter()
+                    .all(|bonus| bonus.duration > 0 && bonus.bonus_permille_u64 <= 1000),
+            ContractError::InvalidLockup {}
+        );
+        Ok(self)
+    }
+
+    // The longest scheduled lock not above `lock_duration`
+    pub fn bonus(&self, lock_duration: u64) -> Option<&LockBonus> {
+        if !self.enabled {
+            return None;
+        }
+        self.bonuses
+            .iter()
+            .rev()
+            .find(|bonus| bonus.duration <= lock_duration)
+    }
+}
+
+pub const LOCKUP: Item<Lockup> = Item::new("lockup");
+
+#[cw_serde]
+pub struct Lock {
+    pub id: u64,
+    pub owner: Addr,
+    pub denom: String,
+    // Bonus included
+    pub amount: Uint128,
+    pub bonus: Uint128,
+    pub unlocks_at: Timestamp,
+}
+
+// Removed once withdrawn
+pub const LOCKS: Map<(&Addr, u64), Lock> = Map::new("locks");
+pub const NEXT_LOCK_ID: Item<u64> = Item::new("next_lock_id");
+
+#[cw_serde]
+pub struct SwapSimulation {
+    // Bonus included
+    pub amount_out: Uint128,
+    pub bonus: Uint128,
+    // Left under the cap after the swap
+    pub remaining: Uint128,
+    pub unlocks_at: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub struct Redemption {
+    pub enabled: bool,
+    pub starts_at: Timestamp,
+    pub ends_at: Timestamp,
+    // Accepted denom paid per token of the default product
+    pub rate: Decimal,
+}
+
+impl Redemption {
+    pub fn disabled() -> Self {
+        Redemption {
+            enabled: false,
+            starts_at: Timestamp::from_seconds(0),
+            ends_at: Timestamp::from_seconds(0),
+            rate: Decimal::zero(),
+        }
+    }
+
+    // Not paying more than the swap took, `product_rate` being the default product per accepted denom
+    pub fn validate(self, product_rate: Decimal) -> Result<Self, ContractError> {
+        ensure!(
+            self.starts_at < self.ends_at
+                && !self.rate.is_zero()
+                && matches!(self.rate.checked_mul(product_rate), Ok(paid) if paid <= Decimal::one()),
+            ContractError::InvalidRedemption {}
+        );
+        Ok(self)
+    }
+
+    pub fn is_open(&self, now: Timestamp) -> bool {
+        self.enabled && self.starts_at <= now && now < self.ends_at
+    }
+}
+
+pub const REDEMPTION: Item<Redemption> = Item::new("redemption");
+// Accepted denom held for the redemptions
+pub const RESERVE: Item<Uint128> = Item::new("reserve");
+// Default product redeemed once the contract isn't the denom admin anymore, held as it can't be burned
+pub const REDEEMED_HELD: Item<Uint128> = Item::new("redeemed_held");
+
+#[cw_serde]
+pub struct RedemptionStatus {
+    pub redemption: Redemption,
+    pub reserve: Uint128,
+    // Paid if the whole supply of the default product was redeemed
+    pub required: Uint128,
+    pub sufficient: bool,
+}
+
+// CW20 tokens swappable with `Receive`
+pub const CW20_ALLOWLIST: Map<&Addr, Empty> = Map::new("cw20_allowlist");
+// Per CW20 token, `STATS` only has the accepted denom
+pub const CW20_STATS: Map<&Addr, Statistics> = Map::new("cw20_stats");
+
+#[cw_serde]
+pub struct IbcForward {
+    pub channel: String,
+    pub receiver: String,
+    // In seconds from the swap
+    pub timeout: u64,
+}
+
+impl IbcForward {
+    pub fn validate(self) -> Result<Self, ContractError> {
+        ensure!(
+            self.channel.starts_with("channel-") && !self.receiver.is_empty() && self.timeout > 0,
+            ContractError::InvalidIbcForward {}
+        );
+        Ok(self)
+    }
+}
+
+#[cw_serde]
+pub struct PendingIbcForward {
+    // Gets the product back if the transfer fails
+    pub refund_to: Addr,
+    pub channel: String,
+    pub amount: Coin,
+}
+
+// Forward waiting for the reply of its `IbcMsg::Transfer`
+pub const PENDING_IBC_FORWARD: Item<PendingIbcForward> = Item::new("pending_ibc_forward");
+
+// Forwards sent, waiting for the ibc-hooks callback, keyed by (channel, sequence)
+pub const IBC_FORWARDS: Map<(&str, u64), PendingIbcForward> = Map::new("ibc_forwards");
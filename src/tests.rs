@@ -1,13 +1,38 @@
 #![cfg(test)]
 mod tests {
-    use cosmwasm_std::{coin, Addr, Coin, Decimal, StdResult, Uint128};
-    use cw_multi_test::{BankSudo, Contract, ContractWrapper, Executor, SudoMsg};
-    use token_bindings::{TokenFactoryMsg, TokenFactoryQuery};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, MockStorage};
+    use cosmwasm_std::{
+        coin, to_binary, Addr, Binary, Coin, CosmosMsg, Decimal, DepsMut, Env, MessageInfo,
+        QuerierWrapper, Reply, Response, StdResult, SubMsgResponse, SubMsgResult, Uint128,
+    };
+    use cw2::set_contract_version;
+    use cw20::{BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg, TokenInfoResponse};
+    use cw_multi_test::{AppResponse, BankSudo, Contract, ContractWrapper, Executor, SudoMsg};
+    use token_bindings::{AdminResponse, DenomUnit, TokenFactoryMsg, TokenFactoryQuery};
     use token_bindings_test::TokenFactoryApp;
 
-    use crate::constants::{BALANCE_MAX_SUPPLY, JUNO_MAX_SUPPLY};
-    use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-    use crate::state::{BurnedSnapshot, Config, Statistics};
+    use crate::constants::{
+        BALANCE_MAX_SUPPLY, IBC_FORWARD_REPLY_ID, JUNO_MAX_SUPPLY, MSG_TRANSFER_TYPE_URL,
+        RENOUNCED_ADMIN,
+    };
+    use crate::contract::{CONTRACT_NAME, CONTRACT_VERSION};
+    use crate::error::ContractError;
+    use crate::msg::{
+        ExecuteMsg, IbcLifecycleComplete, InstantiateMsg, QueryMsg, ReceiveMsg,
+        SudoMsg as ContractSudoMsg,
+    };
+    use crate::state::{
+        BurnCrank, BurnDestination, BurnRecord, BurnedSnapshot, CircuitBreaker,
+        CircuitBreakerStatus, Config, DenomMetadata, IbcForward, Lifecycle, Operation,
+        OperationStatus, Phase, Product, Redemption, RedemptionStatus, Role, RoleMembers,
+        SnapshotDebt, Statistics, SupplyInfo, SwapAllowance, SwapLimits, WindowLimit,
+        BURNED_REMINTED_SNAPSHOT,
+    };
+    use crate::state::{
+        InvariantViolation, Lock, LockBonus, Lockup, Referral, ReferralSource, ReferrerStats,
+        SwapSimulation, Vesting, VestingPosition, VestingTier, CONFIG,
+    };
+    use cw_utils::Duration;
 
     pub const ADMIN: &str = "juno1admin";
     pub const JUNO_DENOM: &str = "ujuno";
@@ -16,6 +41,8 @@ mod tests {
     pub const BAL_DEV_FUND: &str = "juno1balancefund";
     pub const JUNO_DEV_FUND: &str = "juno1junofund";
     pub const DEV: &str = "juno1dev";
+    pub const GUARDIAN: &str = "juno1guardian";
+    pub const PAUSER: &str = "juno1pauser";
 
     fn mock_app() -> TokenFactoryApp {
         TokenFactoryApp::default()
@@ -27,10 +54,132 @@ mod tests {
             crate::contract::instantiate,
             crate::contract::query,
         )
+        .with_reply(crate::contract::reply)
+        .with_sudo(crate::contract::sudo)
         .with_migrate(crate::contract::migrate);
         Box::new(contract)
     }
 
+    // The mocked chain rejects the stargate `MsgTransfer`, the reply gets the packet sequence
+    // (the block height) as the transfer module would return it
+    fn ibc_transfer_contract_box_def() -> Box<dyn Contract<TokenFactoryMsg, TokenFactoryQuery>> {
+        let contract = ContractWrapper::new(
+            crate::contract::execute,
+            crate::contract::instantiate,
+            crate::contract::query,
+        )
+        .with_reply(
+            |deps: DepsMut<TokenFactoryQuery>,
+             env: Env,
+             mut msg: Reply|
+             -> Result<Response<TokenFactoryMsg>, ContractError> {
+                if msg.id == IBC_FORWARD_REPLY_ID {
+                    let mut data = vec![0x08];
+                    let mut sequence = env.block.height;
+                    while sequence >= 0x80 {
+                        data.push((sequence & 0x7f) as u8 | 0x80);
+                        sequence >>= 7;
+                    }
+                    data.push(sequence as u8);
+                    msg.result = SubMsgResult::Ok(SubMsgResponse {
+                        events: vec![],
+                        data: Some(Binary::from(data)),
+                    });
+                }
+                crate::contract::reply(deps, env, msg)
+            },
+        )
+        .with_sudo(crate::contract::sudo)
+        .with_migrate(crate::contract::migrate);
+        Box::new(contract)
+    }
+
+    // Instantiates with the given stored contract version, as deployed before the migrations
+    fn instantiate_with_version(
+        mut deps: DepsMut<TokenFactoryQuery>,
+        env: Env,
+        info: MessageInfo,
+        init_msg: InstantiateMsg,
+        name: &str,
+        version: &str,
+    ) -> Result<Response<TokenFactoryMsg>, ContractError> {
+        let response = crate::contract::instantiate(deps.branch(), env, info, init_msg)?;
+        set_contract_version(deps.storage, name, version)?;
+        Ok(response)
+    }
+
+    fn legacy_contract_box_def() -> Box<dyn Contract<TokenFactoryMsg, TokenFactoryQuery>> {
+        let contract = ContractWrapper::new(
+            crate::contract::execute,
+            |deps, env, info, init_msg| {
+                instantiate_with_version(deps, env, info, init_msg, CONTRACT_NAME, "0.1.0")
+            },
+            crate::contract::query,
+        )
+        .with_reply(crate::contract::reply)
+        .with_sudo(crate::contract::sudo)
+        .with_migrate(crate::contract::migrate);
+        Box::new(contract)
+    }
+
+    fn swap_msg() -> ExecuteMsg {
+        ExecuteMsg::Swap {
+            product: None,
+            referrer: None,
+            lock_duration: None,
+            recipient: None,
+            forward_ibc: None,
+        }
+    }
+
+    fn init_msg() -> InstantiateMsg {
+        InstantiateMsg {
+            accepted_denom: JUNO_DENOM.to_string(),
+            subdenom: "balance".to_string(),
+            balance_development_fund_addr: BAL_DEV_FUND.to_string(),
+            juno_development_fund_addr: JUNO_DEV_FUND.to_string(),
+            dev_addr: DEV.to_string(),
+            burn_permille_u64: 780,
+            dev_fees_permille_u64: 20,
+            balance_development_fund_permille_u64: 100,
+            juno_development_fund_permille_u64: 100,
+            swap_limits: None,
+            circuit_breaker: None,
+            denom_metadata: None,
+            burn_destination: None,
+        }
+    }
+
+    // Funds the wallets with JUNO and instantiates the contract with the given message
+    fn setup(app: &mut TokenFactoryApp, init_msg: InstantiateMsg) -> Addr {
+        setup_contract(app, contract_box_def(), init_msg)
+    }
+
+    fn setup_contract(
+        app: &mut TokenFactoryApp,
+        contract: Box<dyn Contract<TokenFactoryMsg, TokenFactoryQuery>>,
+        init_msg: InstantiateMsg,
+    ) -> Addr {
+        for address in [ADMIN, WALLET1] {
+            app.sudo(SudoMsg::Bank(BankSudo::Mint {
+                to_address: address.to_string(),
+                amount: vec![coin(200_000_000_000_000u128, JUNO_DENOM)],
+            }))
+            .unwrap();
+        }
+
+        let contract_id = app.store_code(contract);
+        app.instantiate_contract(
+            contract_id,
+            Addr::unchecked(ADMIN),
+            &init_msg,
+            &[],
+            "balance_swap",
+            Some(ADMIN.to_string()),
+        )
+        .unwrap()
+    }
+
     #[test]
     fn integration_tests() {
         let mut app = mock_app();
@@ -53,14 +202,15 @@ mod tests {
             .unwrap();
         }
 
-        // Init contract
-        let contract_id = app.store_code(contract_box_def());
+        // Init contract, as deployed before 0.1.1
+        let contract_id = app.store_code(legacy_contract_box_def());
         let contract_addr = app
             .instantiate_contract(
                 contract_id.clone(),
                 Addr::unchecked(ADMIN),
                 &InstantiateMsg {
                     accepted_denom: JUNO_DENOM.to_string(),
+                    subdenom: "balance".to_string(),
                     balance_development_fund_addr: BAL_DEV_FUND.to_string(),
                     juno_development_fund_addr: JUNO_DEV_FUND.to_string(),
                     dev_addr: DEV.to_string(),
@@ -68,6 +218,10 @@ mod tests {
                     dev_fees_permille_u64: 20,
                     balance_development_fund_permille_u64: 100,
                     juno_development_fund_permille_u64: 100,
+                    swap_limits: None,
+                    circuit_breaker: None,
+                    denom_metadata: None,
+                    burn_destination: None,
                 },
                 &[],
                 "balance_swap",
@@ -79,7 +233,7 @@ mod tests {
         let execute_outcome = app.execute_contract(
             Addr::unchecked(WALLET1),
             contract_addr.clone(),
-            &ExecuteMsg::Swap {},
+            &swap_msg(),
             &[Coin {
                 denom: INVALID_DENOM.to_string(),
                 amount: Uint128::new(1_000_000u128),
@@ -87,14 +241,38 @@ mod tests {
         );
         assert_eq!(
             execute_outcome.unwrap_err().source().unwrap().to_string(),
-            "InvalidFundsReceived".to_string()
+            "WrongDenom - expected: ujuno, received: uinvalid".to_string()
+        );
+
+        // Swap several coins
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000u128, INVALID_DENOM), coin(1_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "MultipleCoins - expected: ujuno, received: 1000uinvalid,1000ujuno".to_string()
+        );
+
+        // Swap without funds
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "ZeroAmount - funds".to_string()
         );
 
         // Swap more than max supply
         let execute_outcome = app.execute_contract(
             Addr::unchecked(WALLET1),
             contract_addr.clone(),
-            &ExecuteMsg::Swap {},
+            &swap_msg(),
             &[Coin {
                 denom: JUNO_DENOM.to_string(),
                 amount: Uint128::new(190_000_000_000_000u128),
@@ -102,7 +280,8 @@ mod tests {
         );
         assert_eq!(
             execute_outcome.unwrap_err().source().unwrap().to_string(),
-            "MaxSupplyReached".to_string()
+            "MaxSupplyReceivedReached - amount: 190000000000000, remaining: 185562268000000"
+                .to_string()
         );
 
         // Try to enable disable without being admin
@@ -203,7 +382,7 @@ mod tests {
         let execute_outcome = app.execute_contract(
             Addr::unchecked(WALLET1),
             contract_addr.clone(),
-            &ExecuteMsg::Swap {},
+            &swap_msg(),
             &[Coin {
                 denom: JUNO_DENOM.to_string(),
                 amount: Uint128::new(100_000_000u128),
@@ -227,7 +406,7 @@ mod tests {
         let execute_outcome = app.execute_contract(
             Addr::unchecked(WALLET1),
             contract_addr.clone(),
-            &ExecuteMsg::Swap {},
+            &swap_msg(),
             &[Coin {
                 denom: JUNO_DENOM.to_string(),
                 amount: Uint128::new(100_000_000u128),
@@ -318,7 +497,7 @@ mod tests {
             let execute_outcome = app.execute_contract(
                 Addr::unchecked(WALLET1),
                 contract_addr.clone(),
-                &ExecuteMsg::Swap {},
+                &swap_msg(),
                 &[Coin {
                     denom: JUNO_DENOM.to_string(),
                     amount: Uint128::new(i * 100_000_000u128),
@@ -419,7 +598,7 @@ mod tests {
         let execute_outcome = app.execute_contract(
             Addr::unchecked(WALLET1),
             contract_addr.clone(),
-            &ExecuteMsg::Swap {},
+            &swap_msg(),
             &[Coin {
                 denom: JUNO_DENOM.to_string(),
                 amount: Uint128::new(100_000_000u128),
@@ -514,4 +693,3594 @@ mod tests {
             .unwrap();
         assert_eq!(final_contract.amount, Uint128::new(78_000_000u128 + 74_646_000_000u128 + 78_000_000u128));
     }
+
+    #[test]
+    fn swap_limits() {
+        let mut app = mock_app();
+        let contract_addr = setup(
+            &mut app,
+            InstantiateMsg {
+                swap_limits: Some(SwapLimits {
+                    per_tx_max: Some(Uint128::new(1_000_000_000u128)),
+                    per_address_max: Some(Uint128::new(1_500_000_000u128)),
+                    per_address_window: Some(WindowLimit {
+                        window: Duration::Time(86_400),
+                        max: Uint128::new(1_200_000_000u128),
+                    }),
+                }),
+                ..init_msg()
+            },
+        );
+
+        let swap = |app: &mut TokenFactoryApp, amount: u128| {
+            app.execute_contract(
+                Addr::unchecked(WALLET1),
+                contract_addr.clone(),
+                &swap_msg(),
+                &[coin(amount, JUNO_DENOM)],
+            )
+        };
+
+        // Above the per transaction max
+        let execute_outcome = swap(&mut app, 1_000_000_001u128);
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SwapLimitPerTxExceeded - remaining: 1000000000".to_string()
+        );
+
+        assert!(swap(&mut app, 1_000_000_000u128).is_ok());
+
+        // Above what is left in the window
+        let execute_outcome = swap(&mut app, 300_000_000u128);
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SwapLimitPerWindowExceeded - remaining: 200000000".to_string()
+        );
+
+        // Still within the rolling window
+        app.update_block(|block| block.time = block.time.plus_seconds(43_200));
+        let execute_outcome = swap(&mut app, 300_000_000u128);
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SwapLimitPerWindowExceeded - remaining: 200000000".to_string()
+        );
+
+        // The first swap left the window, but above what is left for the address
+        app.update_block(|block| block.time = block.time.plus_seconds(46_800));
+        let execute_outcome = swap(&mut app, 600_000_000u128);
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SwapLimitPerAddressExceeded - remaining: 500000000".to_string()
+        );
+
+        assert!(swap(&mut app, 500_000_000u128).is_ok());
+
+        let allowance_query: SwapAllowance = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetSwapAllowance {
+                    address: WALLET1.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            allowance_query.per_tx,
+            Some(Uint128::new(1_000_000_000u128))
+        );
+        assert_eq!(allowance_query.per_address, Some(Uint128::zero()));
+        assert_eq!(
+            allowance_query.per_window,
+            Some(Uint128::new(700_000_000u128))
+        );
+
+        // Only the owner can update the limits
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateSwapLimits {
+                swap_limits: SwapLimits::unlimited(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateSwapLimits {
+                swap_limits: SwapLimits::unlimited(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let limits_query: SwapLimits = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetSwapLimits {})
+            .unwrap();
+        assert_eq!(limits_query, SwapLimits::unlimited());
+        assert!(swap(&mut app, 1_000_000_001u128).is_ok());
+    }
+
+    #[test]
+    fn circuit_breaker() {
+        let mut app = mock_app();
+        let contract_addr = setup(
+            &mut app,
+            InstantiateMsg {
+                circuit_breaker: Some(CircuitBreaker {
+                    window: Duration::Time(3_600),
+                    max_volume: Uint128::new(1_000_000_000u128),
+                    guardian: Some(GUARDIAN.to_string()),
+                }),
+                ..init_msg()
+            },
+        );
+
+        let swap = |app: &mut TokenFactoryApp, amount: u128| {
+            app.execute_contract(
+                Addr::unchecked(WALLET1),
+                contract_addr.clone(),
+                &swap_msg(),
+                &[coin(amount, JUNO_DENOM)],
+            )
+        };
+
+        assert!(swap(&mut app, 600_000_000u128).is_ok());
+
+        // The swap crossing the max volume is refunded and trips the breaker
+        let wallet1_juno = app.wrap().query_balance(WALLET1, JUNO_DENOM).unwrap();
+        let execute_outcome = swap(&mut app, 500_000_000u128).unwrap();
+        assert!(execute_outcome
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-circuit_breaker_tripped"));
+        assert_eq!(
+            app.wrap().query_balance(WALLET1, JUNO_DENOM).unwrap(),
+            wallet1_juno
+        );
+
+        let status_query: CircuitBreakerStatus = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetCircuitBreaker {})
+            .unwrap();
+        assert_eq!(status_query.volume.tripped_at, Some(app.block_info().time));
+        assert_eq!(status_query.window_amount, Uint128::new(600_000_000u128));
+        let lifecycle_query: Lifecycle = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetLifecycle {})
+            .unwrap();
+        assert_eq!(lifecycle_query.phase, Phase::Paused);
+
+        // Stays paused, even in a new window
+        app.update_block(|block| block.time = block.time.plus_seconds(3_600));
+        let execute_outcome = swap(&mut app, 1_000_000u128);
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "CircuitBreakerTripped".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::ResetCircuitBreaker {},
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(GUARDIAN),
+            contract_addr.clone(),
+            &ExecuteMsg::ResetCircuitBreaker {},
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let lifecycle_query: Lifecycle = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetLifecycle {})
+            .unwrap();
+        assert_eq!(lifecycle_query.phase, Phase::Active);
+
+        // The window rolls, the swaps of the last hour are counted
+        assert!(swap(&mut app, 500_000_000u128).is_ok());
+        app.update_block(|block| block.time = block.time.plus_seconds(1_800));
+        assert!(swap(&mut app, 400_000_000u128).is_ok());
+        app.update_block(|block| block.time = block.time.plus_seconds(1_950));
+        let execute_outcome = swap(&mut app, 600_000_000u128).unwrap();
+        assert!(!execute_outcome
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-circuit_breaker_tripped"));
+        let status_query: CircuitBreakerStatus = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::GetCircuitBreaker {})
+            .unwrap();
+        assert_eq!(status_query.volume.tripped_at, None);
+        assert_eq!(status_query.window_amount, Uint128::new(1_000_000_000u128));
+    }
+
+    #[test]
+    fn roles() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        // Only owners can grant roles
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::GrantRole {
+                role: Role::Pauser,
+                address: WALLET1.to_string(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::GrantRole {
+                role: Role::Pauser,
+                address: PAUSER.to_string(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let members_query: RoleMembers = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetRoleMembers {
+                    role: Role::Pauser,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(members_query.members, vec![PAUSER.to_string()]);
+
+        let roles_query: Vec<Role> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetRoles {
+                    address: PAUSER.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(roles_query, vec![Role::Pauser]);
+
+        let roles_query: Vec<Role> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetRoles {
+                    address: ADMIN.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(roles_query, Role::all());
+
+        // The pauser can disable but not enable
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(PAUSER),
+            contract_addr.clone(),
+            &ExecuteMsg::EnableDisable {},
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(PAUSER),
+            contract_addr.clone(),
+            &ExecuteMsg::EnableDisable {},
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        // Nor change the config
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(PAUSER),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateSwapLimits {
+                swap_limits: SwapLimits::unlimited(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::RevokeRole {
+                role: Role::Pauser,
+                address: PAUSER.to_string(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let members_query: RoleMembers = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetRoleMembers {
+                    role: Role::Pauser,
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert!(members_query.members.is_empty());
+    }
+
+    #[test]
+    fn pause_unpause() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::GrantRole {
+                role: Role::Pauser,
+                address: PAUSER.to_string(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(PAUSER),
+            contract_addr.clone(),
+            &ExecuteMsg::Pause {
+                operations: Some(vec![Operation::Swap, Operation::Burn]),
+                reason: Some("incident".to_string()),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let paused_at = app.block_info().time;
+
+        // Pausing again does not unpause nor override the reason
+        app.next_block();
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::Pause {
+                operations: Some(vec![Operation::Swap]),
+                reason: Some("other".to_string()),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let status_query: Vec<OperationStatus> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetPauseStatus {})
+            .unwrap();
+        assert_eq!(status_query.len(), 3);
+        let swap_pause = status_query[0].paused.clone().unwrap();
+        assert_eq!(status_query[0].operation, Operation::Swap);
+        assert_eq!(swap_pause.reason, Some("incident".to_string()));
+        assert_eq!(swap_pause.paused_at, paused_at);
+        assert_eq!(swap_pause.paused_by, PAUSER.to_string());
+        assert!(status_query[1].paused.is_some());
+        assert!(status_query[2].paused.is_none());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SwapDisabled".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::Burn {},
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "OperationPaused - burn".to_string()
+        );
+
+        // Pausers cannot unpause
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(PAUSER),
+            contract_addr.clone(),
+            &ExecuteMsg::Unpause { operations: None },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::Unpause { operations: None },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let config_query: Config = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetConfig {})
+            .unwrap();
+        assert!(config_query.enabled);
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+    }
+
+    #[test]
+    fn denom_metadata() {
+        let mut app = mock_app();
+        let metadata = DenomMetadata {
+            description: Some("BalanceDAO token".to_string()),
+            denom_units: vec![DenomUnit {
+                denom: "balance".to_string(),
+                exponent: 6,
+                aliases: vec![],
+            }],
+            display: "balance".to_string(),
+            name: "Balance".to_string(),
+            symbol: "BALANCE".to_string(),
+            uri: None,
+        };
+        let contract_addr = setup(
+            &mut app,
+            InstantiateMsg {
+                denom_metadata: Some(metadata.clone()),
+                ..init_msg()
+            },
+        );
+
+        // The base unit is added
+        let metadata_query: Option<DenomMetadata> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetDenomMetadata {})
+            .unwrap();
+        let metadata_query = metadata_query.unwrap();
+        assert_eq!(metadata_query.denom_units.len(), 2);
+        assert_eq!(
+            metadata_query.denom_units[0].denom,
+            "factory/contract0/balance".to_string()
+        );
+        assert_eq!(metadata_query.denom_units[0].exponent, 0);
+
+        let updated_metadata = DenomMetadata {
+            uri: Some("https://balancedao.io/balance.png".to_string()),
+            ..metadata
+        };
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::SetDenomMetadata {
+                metadata: updated_metadata.clone(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::SetDenomMetadata {
+                metadata: DenomMetadata {
+                    display: "unknown".to_string(),
+                    ..updated_metadata.clone()
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidDenomMetadata".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::SetDenomMetadata {
+                metadata: updated_metadata,
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let metadata_query: Option<DenomMetadata> = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::GetDenomMetadata {})
+            .unwrap();
+        assert_eq!(
+            metadata_query.unwrap().uri,
+            Some("https://balancedao.io/balance.png".to_string())
+        );
+    }
+
+    #[test]
+    fn products() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::CreateProduct {
+                subdenom: "lbalance".to_string(),
+                rate: Decimal::percent(50),
+                cap: Uint128::new(1_000u128),
+                metadata: None,
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::CreateProduct {
+                subdenom: "balance".to_string(),
+                rate: Decimal::percent(50),
+                cap: Uint128::new(1_000u128),
+                metadata: None,
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "ProductAlreadyExists".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::CreateProduct {
+                subdenom: "lbalance".to_string(),
+                rate: Decimal::percent(50),
+                cap: Uint128::new(1_000u128),
+                metadata: None,
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Swap {
+                product: Some("factory/contract0/lbalance".to_string()),
+                referrer: None,
+                lock_duration: None,
+                recipient: None,
+                forward_ibc: None,
+            },
+            &[coin(1_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let wallet1_lbalance: Coin = app
+            .wrap()
+            .query_balance(WALLET1, "factory/contract0/lbalance".to_string())
+            .unwrap();
+        assert_eq!(wallet1_lbalance.amount, Uint128::new(500u128));
+
+        // Above the product cap
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Swap {
+                product: Some("factory/contract0/lbalance".to_string()),
+                referrer: None,
+                lock_duration: None,
+                recipient: None,
+                forward_ibc: None,
+            },
+            &[coin(1_002u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "MaxSupplyReached - denom: factory/contract0/lbalance, amount: 501, remaining: 500"
+                .to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Swap {
+                product: Some("factory/contract0/unknown".to_string()),
+                referrer: None,
+                lock_duration: None,
+                recipient: None,
+                forward_ibc: None,
+            },
+            &[coin(1_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "UnknownProduct".to_string()
+        );
+
+        let products_query: Vec<Product> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::ListProducts {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(products_query.len(), 2);
+        assert_eq!(products_query[0].denom, "factory/contract0/balance");
+        assert_eq!(products_query[0].cap, BALANCE_MAX_SUPPLY);
+        assert_eq!(products_query[1].denom, "factory/contract0/lbalance");
+        assert_eq!(products_query[1].minted, Uint128::new(500u128));
+
+        let product_query: Product = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::GetProduct { denom: None })
+            .unwrap();
+        assert_eq!(
+            product_query.rate,
+            Decimal::from_ratio(BALANCE_MAX_SUPPLY, JUNO_MAX_SUPPLY)
+        );
+    }
+
+    #[test]
+    fn denom_admin() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let change_admin_msg = ExecuteMsg::ChangeDenomAdmin {
+            denom: None,
+            new_admin: DEV.to_string(),
+        };
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &change_admin_msg,
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        // Swapping is still possible
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &change_admin_msg,
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "MintingNotFinished".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::BurnFrom {
+                denom: None,
+                amount: Uint128::zero(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "ZeroAmount - amount".to_string()
+        );
+
+        // Burns what holders sent to the contract
+        let denom = "factory/contract0/balance";
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        app.send_tokens(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &[coin(10_000u128, denom)],
+        )
+        .unwrap();
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::BurnFrom {
+                denom: None,
+                amount: Uint128::new(20_000u128),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "BurnExceedsBalance - balance: 10000, amount: 20000".to_string()
+        );
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::BurnFrom {
+                denom: None,
+                amount: Uint128::new(10_000u128),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        assert_eq!(
+            app.wrap()
+                .query_balance(&contract_addr, denom)
+                .unwrap()
+                .amount,
+            Uint128::zero()
+        );
+        assert_eq!(
+            app.wrap().query_balance(WALLET1, denom).unwrap().amount,
+            Uint128::new(103_169u128)
+        );
+        // The burned amount is recorded, without freeing room under the cap
+        let supply_query: SupplyInfo = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetSupplyInfo { denom: None },
+            )
+            .unwrap();
+        assert_eq!(supply_query.recorded, Uint128::new(103_169u128));
+        assert_eq!(supply_query.bank_supply, Uint128::new(103_169u128));
+        assert_eq!(
+            supply_query.remaining,
+            BALANCE_MAX_SUPPLY - Uint128::new(113_169u128)
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::Pause {
+                operations: Some(vec![Operation::Swap]),
+                reason: None,
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::ChangeDenomAdmin {
+                denom: None,
+                new_admin: contract_addr.to_string(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidDenomAdmin".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &change_admin_msg,
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let product_query: Product = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetProduct { denom: None })
+            .unwrap();
+        assert_eq!(product_query.admin_transferred_to, Some(DEV.to_string()));
+
+        // Nothing can be minted nor burned by the contract anymore
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::Unpause {
+                operations: Some(vec![Operation::Swap]),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "DenomAdminTransferred".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &change_admin_msg,
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "DenomAdminTransferred".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr,
+            &ExecuteMsg::BurnFrom {
+                denom: None,
+                amount: Uint128::new(1u128),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "DenomAdminTransferred".to_string()
+        );
+    }
+
+    #[test]
+    fn finalization() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let lifecycle_query: Lifecycle = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetLifecycle {})
+            .unwrap();
+        assert_eq!(lifecycle_query.phase, Phase::Active);
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Finalize {},
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "MintingNotFinished".to_string()
+        );
+
+        // Juno sent outside of a swap gets swept to the buckets
+        app.sudo(SudoMsg::Bank({
+            BankSudo::Mint {
+                to_address: contract_addr.to_string(),
+                amount: vec![coin(1_000u128, JUNO_DENOM)],
+            }
+        }))
+        .unwrap();
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[Coin {
+                denom: JUNO_DENOM.to_string(),
+                amount: JUNO_MAX_SUPPLY,
+            }],
+        );
+        assert!(execute_outcome.is_ok());
+        assert_eq!(
+            app.wrap()
+                .query_balance(WALLET1, "factory/contract0/balance")
+                .unwrap()
+                .amount,
+            Uint128::new(20_999_999_999_999u128)
+        );
+
+        let lifecycle_query: Lifecycle = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetLifecycle {})
+            .unwrap();
+        assert_eq!(lifecycle_query.phase, Phase::Finalized);
+        assert_eq!(lifecycle_query.finalized_at, Some(app.block_info().time));
+
+        let bal_dev_fund_balance: Coin = app
+            .wrap()
+            .query_balance(BAL_DEV_FUND, JUNO_DENOM.to_string())
+            .unwrap();
+        assert_eq!(
+            bal_dev_fund_balance.amount,
+            Uint128::new(18_556_226_800_000u128 + 100u128)
+        );
+
+        // Only the Juno to burn is left
+        let to_burn_query: Coin = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetToBurn {})
+            .unwrap();
+        let contract_balance: Coin = app
+            .wrap()
+            .query_balance(contract_addr.to_string(), JUNO_DENOM.to_string())
+            .unwrap();
+        assert_eq!(contract_balance.amount, to_burn_query.amount);
+
+        let product_query: Product = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetProduct { denom: None })
+            .unwrap();
+        assert_eq!(
+            product_query.admin_transferred_to,
+            Some(RENOUNCED_ADMIN.to_string())
+        );
+        let admin_query: AdminResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetDenomAdmin { denom: None },
+            )
+            .unwrap();
+        assert_eq!(admin_query.admin, RENOUNCED_ADMIN.to_string());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SwapFinalized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr,
+            &ExecuteMsg::Finalize {},
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SwapFinalized".to_string()
+        );
+    }
+
+    #[test]
+    fn supply_info() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(!execute_outcome
+            .unwrap()
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-supply_mismatch"));
+
+        let supply_query: SupplyInfo = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetSupplyInfo { denom: None },
+            )
+            .unwrap();
+        assert_eq!(supply_query.denom, "factory/contract0/balance");
+        assert_eq!(supply_query.recorded, supply_query.bank_supply);
+        assert_eq!(
+            supply_query.remaining,
+            BALANCE_MAX_SUPPLY - supply_query.recorded
+        );
+
+        // Minted outside of the contract
+        app.sudo(SudoMsg::Bank({
+            BankSudo::Mint {
+                to_address: DEV.to_string(),
+                amount: vec![coin(1_000u128, "factory/contract0/balance")],
+            }
+        }))
+        .unwrap();
+
+        let supply_query: SupplyInfo = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetSupplyInfo { denom: None },
+            )
+            .unwrap();
+        assert_eq!(
+            supply_query.bank_supply,
+            supply_query.recorded + Uint128::new(1_000u128)
+        );
+        assert_eq!(
+            supply_query.remaining,
+            BALANCE_MAX_SUPPLY - supply_query.bank_supply
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr,
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome
+            .unwrap()
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-supply_mismatch"));
+    }
+
+    #[test]
+    fn zero_payouts() {
+        let mut app = mock_app();
+        let contract_addr = setup(
+            &mut app,
+            InstantiateMsg {
+                burn_permille_u64: 800,
+                dev_fees_permille_u64: 0,
+                ..init_msg()
+            },
+        );
+
+        // No payout is sent to a bucket without a share
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(!execute_outcome
+            .unwrap()
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-payout_failed"));
+        let claim_query: Uint128 = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr,
+                &QueryMsg::GetClaim {
+                    address: DEV.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(claim_query, Uint128::zero());
+    }
+
+    #[test]
+    fn replies_and_claims() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        // The denom is confirmed by the reply to `CreateDenom`
+        let config_query: Config = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetConfig {})
+            .unwrap();
+        assert_eq!(config_query.factory_denom, "factory/contract0/balance");
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        // Payouts sent successfully leave nothing to claim
+        let claim_query: Uint128 = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetClaim {
+                    address: DEV.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(claim_query, Uint128::zero());
+        let dev_balance: Coin = app
+            .wrap()
+            .query_balance(DEV, JUNO_DENOM.to_string())
+            .unwrap();
+        assert_eq!(dev_balance.amount, Uint128::new(20_000u128));
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(DEV),
+            contract_addr.clone(),
+            &ExecuteMsg::Claim {},
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "NothingToClaim".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::Pause {
+                operations: Some(vec![Operation::Claims]),
+                reason: None,
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(DEV),
+            contract_addr,
+            &ExecuteMsg::Claim {},
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "OperationPaused - claims".to_string()
+        );
+    }
+
+    #[test]
+    fn burn_destination() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let burn_destination_query: BurnDestination = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetBurnDestination {})
+            .unwrap();
+        assert_eq!(burn_destination_query, BurnDestination::Hold);
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let stats_query: Statistics = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetStats {})
+            .unwrap();
+        assert_eq!(stats_query.burned, Uint128::new(780_000u128));
+        assert_eq!(stats_query.burn_held, Uint128::new(780_000u128));
+        assert_eq!(stats_query.burn_community_pool, Uint128::zero());
+        assert_eq!(stats_query.burn_contract, Uint128::zero());
+
+        let burn_destination = BurnDestination::Contract {
+            address: "juno1burner".to_string(),
+            msg: to_binary(&ExecuteMsg::Burn {}).unwrap(),
+        };
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateBurnDestination {
+                burn_destination: burn_destination.clone(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateBurnDestination {
+                burn_destination: burn_destination.clone(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let burn_destination_query: BurnDestination = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::GetBurnDestination {})
+            .unwrap();
+        assert_eq!(burn_destination_query, burn_destination);
+    }
+
+    #[test]
+    fn burn_crank() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let crank = |app: &mut TokenFactoryApp| {
+            app.execute_contract(
+                Addr::unchecked(WALLET1),
+                contract_addr.clone(),
+                &ExecuteMsg::CrankBurn {},
+                &[],
+            )
+        };
+        let swap = |app: &mut TokenFactoryApp| {
+            app.execute_contract(
+                Addr::unchecked(WALLET1),
+                contract_addr.clone(),
+                &swap_msg(),
+                &[coin(1_000_000u128, JUNO_DENOM)],
+            )
+        };
+
+        assert!(swap(&mut app).is_ok());
+
+        // Disabled until the new burn module is live
+        assert_eq!(
+            crank(&mut app).unwrap_err().source().unwrap().to_string(),
+            "NotImplementedYet".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateBurnCrank {
+                burn_crank: BurnCrank {
+                    enabled: true,
+                    min_amount: Uint128::new(1_000_000u128),
+                    min_interval: 3_600,
+                    tip_permille_u64: 200,
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidBurnCrank".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateBurnCrank {
+                burn_crank: BurnCrank {
+                    enabled: true,
+                    min_amount: Uint128::new(1_000_000u128),
+                    min_interval: u64::MAX,
+                    tip_permille_u64: 10,
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidBurnCrank".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateBurnCrank {
+                burn_crank: BurnCrank {
+                    enabled: true,
+                    min_amount: Uint128::new(1_000_000u128),
+                    min_interval: 3_600,
+                    tip_permille_u64: 10,
+                },
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let wallet1_before: Coin = app
+            .wrap()
+            .query_balance(WALLET1, JUNO_DENOM.to_string())
+            .unwrap();
+
+        // Nothing was burned yet
+        assert!(crank(&mut app).is_ok());
+
+        let wallet1_after: Coin = app
+            .wrap()
+            .query_balance(WALLET1, JUNO_DENOM.to_string())
+            .unwrap();
+        assert_eq!(
+            wallet1_after.amount - wallet1_before.amount,
+            Uint128::new(7_800u128)
+        );
+
+        let to_burn_query: Coin = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetToBurn {})
+            .unwrap();
+        assert_eq!(to_burn_query.amount, Uint128::zero());
+
+        // Below the min amount and within the min interval
+        assert!(swap(&mut app).is_ok());
+        assert_eq!(
+            crank(&mut app).unwrap_err().source().unwrap().to_string(),
+            "BurnCrankNotReady".to_string()
+        );
+
+        app.update_block(|block| block.time = block.time.plus_seconds(3_600));
+        assert!(crank(&mut app).is_ok());
+
+        let history_query: Vec<BurnRecord> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetBurnHistory {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(history_query.len(), 2);
+        assert_eq!(history_query[0].id, 0);
+        assert_eq!(history_query[0].amount, coin(772_200u128, JUNO_DENOM));
+        assert_eq!(history_query[0].tip, Uint128::new(7_800u128));
+        assert_eq!(history_query[1].burned_by, WALLET1.to_string());
+        assert_eq!(history_query[1].burned_at, app.block_info().time);
+
+        let history_query: Vec<BurnRecord> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr,
+                &QueryMsg::GetBurnHistory {
+                    start_after: Some(0),
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(history_query.len(), 1);
+        assert_eq!(history_query[0].id, 1);
+    }
+
+    #[test]
+    fn snapshot_debt() {
+        let mut app = mock_app();
+        let contract_addr = setup_contract(&mut app, legacy_contract_box_def(), init_msg());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let contract_id = app.store_code(contract_box_def());
+        let migrate_outcome = app.migrate_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &crate::msg::MigrateMsg {
+                states_update: false,
+            },
+            contract_id,
+        );
+        assert!(migrate_outcome.is_ok());
+
+        let debt_query: SnapshotDebt = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetSnapshotDebt {})
+            .unwrap();
+        assert_eq!(debt_query.snapshot, Uint128::new(780_000u128));
+        assert_eq!(debt_query.remaining, Uint128::new(780_000u128));
+
+        // Burning needs the new burn module, the owner enables the settlement once it's live
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::SettleSnapshotDebt {},
+            &[coin(500_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SnapshotSettlementDisabled".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateSnapshotSettlement { enabled: true },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::SettleSnapshotDebt {},
+            &[coin(500_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::SettleSnapshotDebt {},
+            &[coin(500_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::SettleSnapshotDebt {},
+            &[coin(500_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SnapshotDebtExceeded - remaining: 280000".to_string()
+        );
+
+        let debt_query: SnapshotDebt = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetSnapshotDebt {})
+            .unwrap();
+        assert_eq!(debt_query.settled, Uint128::new(500_000u128));
+        assert_eq!(debt_query.remaining, Uint128::new(280_000u128));
+        assert_eq!(debt_query.last_settled_at, Some(app.block_info().time));
+        assert!(debt_query.enabled);
+
+        // The settlements don't pace the burn crank
+        let history_query: Vec<BurnRecord> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr,
+                &QueryMsg::GetBurnHistory {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert!(history_query.is_empty());
+    }
+
+    #[test]
+    fn migration_already_applied() {
+        let mut app = mock_app();
+        // Migrated to 0.1.1 but still storing 0.1.0
+        let migrated_contract = ContractWrapper::new(
+            crate::contract::execute,
+            |mut deps: DepsMut<TokenFactoryQuery>,
+             env: Env,
+             info,
+             init_msg|
+             -> Result<Response<TokenFactoryMsg>, ContractError> {
+                let response = instantiate_with_version(
+                    deps.branch(),
+                    env.clone(),
+                    info,
+                    init_msg,
+                    CONTRACT_NAME,
+                    "0.1.0",
+                )?;
+                BURNED_REMINTED_SNAPSHOT.save(
+                    deps.storage,
+                    &BurnedSnapshot {
+                        denom: JUNO_DENOM.to_string(),
+                        amount: Uint128::new(500_000u128),
+                        snapshot_time: env.block.time,
+                    },
+                )?;
+                Ok(response)
+            },
+            crate::contract::query,
+        )
+        .with_reply(crate::contract::reply)
+        .with_sudo(crate::contract::sudo)
+        .with_migrate(crate::contract::migrate);
+        let contract_addr = setup_contract(&mut app, Box::new(migrated_contract), init_msg());
+        let snapshot_time = app.block_info().time;
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        app.update_block(|block| block.time = block.time.plus_seconds(3_600));
+
+        let contract_id = app.store_code(contract_box_def());
+        let migrate_outcome = app.migrate_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &crate::msg::MigrateMsg {
+                states_update: false,
+            },
+            contract_id,
+        );
+        assert!(migrate_outcome.is_ok());
+
+        // The Juno to burn and the snapshot are kept
+        let to_burn_query: Coin = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetToBurn {})
+            .unwrap();
+        assert_eq!(to_burn_query.amount, Uint128::new(780_000u128));
+        let snapshot_query: BurnedSnapshot = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetBurnedSnapshot {})
+            .unwrap();
+        assert_eq!(snapshot_query.amount, Uint128::new(500_000u128));
+        assert_eq!(snapshot_query.snapshot_time, snapshot_time);
+
+        let recoverable_query: Vec<Coin> = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::GetRecoverable {})
+            .unwrap();
+        assert!(recoverable_query.is_empty());
+    }
+
+    #[test]
+    fn migration_versions() {
+        let mut app = mock_app();
+        let contract_addr = setup_contract(&mut app, legacy_contract_box_def(), init_msg());
+        let contract_id = app.store_code(contract_box_def());
+
+        let migrate = |app: &mut TokenFactoryApp, contract_addr: &Addr| {
+            app.migrate_contract(
+                Addr::unchecked(ADMIN),
+                contract_addr.clone(),
+                &crate::msg::MigrateMsg {
+                    states_update: false,
+                },
+                contract_id,
+            )
+        };
+        let migrated_attribute = |response: &AppResponse, key: &str| {
+            response
+                .events
+                .iter()
+                .find(|event| event.ty == "wasm-migrated")
+                .and_then(|event| event.attributes.iter().find(|attr| attr.key == key))
+                .map(|attr| attr.value.clone())
+        };
+
+        // Every step from 0.1.0
+        let migrate_outcome = migrate(&mut app, &contract_addr).unwrap();
+        assert_eq!(
+            migrated_attribute(&migrate_outcome, "from"),
+            Some("0.1.0".to_string())
+        );
+        assert_eq!(
+            migrated_attribute(&migrate_outcome, "steps"),
+            Some("0.1.1,0.2.0".to_string())
+        );
+        let version = cw2::query_contract_info(&app, contract_addr.clone()).unwrap();
+        assert_eq!(version.version, CONTRACT_VERSION);
+
+        let products_query: Vec<Product> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::ListProducts {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(products_query.len(), 1);
+
+        // Nothing left to run
+        let migrate_outcome = migrate(&mut app, &contract_addr).unwrap();
+        assert_eq!(
+            migrated_attribute(&migrate_outcome, "steps"),
+            Some("".to_string())
+        );
+
+        // Downgrade
+        let future_contract = ContractWrapper::new(
+            crate::contract::execute,
+            |deps, env, info, init_msg| {
+                instantiate_with_version(deps, env, info, init_msg, CONTRACT_NAME, "9.0.0")
+            },
+            crate::contract::query,
+        )
+        .with_migrate(crate::contract::migrate);
+        let future_addr = setup_contract(&mut app, Box::new(future_contract), init_msg());
+        assert_eq!(
+            migrate(&mut app, &future_addr)
+                .unwrap_err()
+                .source()
+                .unwrap()
+                .to_string(),
+            format!("CannotDowngrade - from 9.0.0 to {}", CONTRACT_VERSION)
+        );
+
+        // Another contract
+        let other_contract = ContractWrapper::new(
+            crate::contract::execute,
+            |deps, env, info, init_msg| {
+                instantiate_with_version(deps, env, info, init_msg, "crates.io:other", "0.1.0")
+            },
+            crate::contract::query,
+        )
+        .with_migrate(crate::contract::migrate);
+        let other_addr = setup_contract(&mut app, Box::new(other_contract), init_msg());
+        assert_eq!(
+            migrate(&mut app, &other_addr)
+                .unwrap_err()
+                .source()
+                .unwrap()
+                .to_string(),
+            "WrongContract - crates.io:other".to_string()
+        );
+    }
+
+    // Config as stored and returned before the addresses were typed
+    #[cosmwasm_schema::cw_serde]
+    struct StringConfig {
+        contract_owner: String,
+        enabled: bool,
+        accepted_denom: String,
+        factory_denom: String,
+        balance_development_fund_addr: String,
+        juno_development_fund_addr: String,
+        dev_addr: String,
+        burn_permille_u64: u64,
+        dev_fees_permille_u64: u64,
+        balance_development_fund_permille_u64: u64,
+        juno_development_fund_permille_u64: u64,
+    }
+
+    fn instantiate_with_invalid_address(
+        mut deps: DepsMut<TokenFactoryQuery>,
+        env: Env,
+        info: MessageInfo,
+        init_msg: InstantiateMsg,
+    ) -> Result<Response<TokenFactoryMsg>, ContractError> {
+        let response =
+            instantiate_with_version(deps.branch(), env, info, init_msg, CONTRACT_NAME, "0.1.1")?;
+        CONFIG.update(deps.storage, |mut config| -> StdResult<_> {
+            config.dev_addr = Addr::unchecked("Juno1Dev");
+            Ok(config)
+        })?;
+        Ok(response)
+    }
+
+    #[test]
+    fn config_addresses() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        // Same response as with the string addresses
+        let config_query: StringConfig = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::GetConfig {})
+            .unwrap();
+        assert_eq!(config_query.contract_owner, ADMIN.to_string());
+        assert_eq!(config_query.dev_addr, DEV.to_string());
+
+        // A stored address which isn't valid fails the migration
+        let invalid_contract = ContractWrapper::new(
+            crate::contract::execute,
+            instantiate_with_invalid_address,
+            crate::contract::query,
+        )
+        .with_migrate(crate::contract::migrate);
+        let invalid_addr = setup_contract(&mut app, Box::new(invalid_contract), init_msg());
+        let contract_id = app.store_code(contract_box_def());
+
+        let migrate_outcome = app.migrate_contract(
+            Addr::unchecked(ADMIN),
+            invalid_addr,
+            &crate::msg::MigrateMsg {
+                states_update: false,
+            },
+            contract_id,
+        );
+        assert_eq!(
+            migrate_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidStoredAddress - dev_addr: Juno1Dev".to_string()
+        );
+    }
+
+    #[test]
+    fn recover_funds() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        // Sent by mistake
+        app.sudo(SudoMsg::Bank({
+            BankSudo::Mint {
+                to_address: contract_addr.to_string(),
+                amount: vec![coin(1_000u128, JUNO_DENOM), coin(5_000u128, INVALID_DENOM)],
+            }
+        }))
+        .unwrap();
+
+        // Only the Juno above the Juno to burn
+        let recoverable_query: Vec<Coin> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetRecoverable {})
+            .unwrap();
+        assert_eq!(
+            recoverable_query,
+            vec![coin(5_000u128, INVALID_DENOM), coin(1_000u128, JUNO_DENOM)]
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::RecoverFunds {
+                denom: INVALID_DENOM.to_string(),
+                amount: Uint128::new(5_000u128),
+                to: WALLET1.to_string(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::RecoverFunds {
+                denom: JUNO_DENOM.to_string(),
+                amount: Uint128::new(1_001u128),
+                to: WALLET1.to_string(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "NotRecoverable - recoverable: 1000".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::RecoverFunds {
+                denom: INVALID_DENOM.to_string(),
+                amount: Uint128::new(5_000u128),
+                to: WALLET1.to_string(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let wallet1_invalid: Coin = app
+            .wrap()
+            .query_balance(WALLET1, INVALID_DENOM.to_string())
+            .unwrap();
+        assert_eq!(wallet1_invalid.amount, Uint128::new(5_000u128));
+
+        let recoverable_query: Vec<Coin> = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::GetRecoverable {})
+            .unwrap();
+        assert_eq!(recoverable_query, vec![coin(1_000u128, JUNO_DENOM)]);
+    }
+
+    #[test]
+    fn invariants() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let invariants_query: Vec<InvariantViolation> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::CheckInvariants {})
+            .unwrap();
+        assert!(invariants_query.is_empty());
+
+        // Minted outside of the contract
+        app.sudo(SudoMsg::Bank({
+            BankSudo::Mint {
+                to_address: DEV.to_string(),
+                amount: vec![coin(1_000u128, "factory/contract0/balance")],
+            }
+        }))
+        .unwrap();
+
+        let invariants_query: Vec<InvariantViolation> = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::CheckInvariants {})
+            .unwrap();
+        assert_eq!(invariants_query.len(), 1);
+        assert_eq!(
+            invariants_query[0].invariant,
+            "supply_within_minted:factory/contract0/balance"
+        );
+        assert_eq!(invariants_query[0].expected, "<= 113169");
+        assert_eq!(invariants_query[0].actual, "114169");
+    }
+
+    #[test]
+    fn non_payable_messages() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+
+        let messages = vec![
+            ExecuteMsg::EnableDisable {},
+            ExecuteMsg::Burn {},
+            ExecuteMsg::UpdateSwapLimits {
+                swap_limits: SwapLimits::unlimited(),
+            },
+            ExecuteMsg::UpdateCircuitBreaker {
+                circuit_breaker: None,
+            },
+            ExecuteMsg::ResetCircuitBreaker {},
+            ExecuteMsg::GrantRole {
+                role: Role::Pauser,
+                address: PAUSER.to_string(),
+            },
+            ExecuteMsg::RevokeRole {
+                role: Role::Pauser,
+                address: PAUSER.to_string(),
+            },
+            ExecuteMsg::Pause {
+                operations: None,
+                reason: None,
+            },
+            ExecuteMsg::Unpause { operations: None },
+            ExecuteMsg::SetDenomMetadata {
+                metadata: DenomMetadata {
+                    description: None,
+                    denom_units: vec![],
+                    display: "balance".to_string(),
+                    name: "Balance".to_string(),
+                    symbol: "BAL".to_string(),
+                    uri: None,
+                },
+            },
+            ExecuteMsg::CreateProduct {
+                subdenom: "other".to_string(),
+                rate: Decimal::one(),
+                cap: Uint128::new(1_000u128),
+                metadata: None,
+            },
+            ExecuteMsg::ChangeDenomAdmin {
+                denom: None,
+                new_admin: WALLET1.to_string(),
+            },
+            ExecuteMsg::Finalize {},
+            ExecuteMsg::UpdateBurnDestination {
+                burn_destination: BurnDestination::Hold,
+            },
+            ExecuteMsg::UpdateBurnCrank {
+                burn_crank: BurnCrank::disabled(),
+            },
+            ExecuteMsg::UpdateReferral {
+                referral: Referral::disabled(),
+            },
+            ExecuteMsg::UpdateVesting {
+                vesting: Vesting::disabled(),
+            },
+            ExecuteMsg::ClaimVested {},
+            ExecuteMsg::UpdateLockup {
+                lockup: Lockup::disabled(),
+            },
+            ExecuteMsg::Withdraw {},
+            ExecuteMsg::UpdateRedemption {
+                redemption: Redemption::disabled(),
+            },
+            ExecuteMsg::WithdrawReserve {
+                amount: Uint128::new(1u128),
+                to: ADMIN.to_string(),
+            },
+            ExecuteMsg::UpdateCw20Allowlist {
+                add: vec![],
+                remove: vec![],
+            },
+            ExecuteMsg::CrankBurn {},
+            ExecuteMsg::UpdateSnapshotSettlement { enabled: true },
+            ExecuteMsg::RecoverFunds {
+                denom: JUNO_DENOM.to_string(),
+                amount: Uint128::new(1u128),
+                to: ADMIN.to_string(),
+            },
+            ExecuteMsg::Claim {},
+            ExecuteMsg::BurnFrom {
+                denom: None,
+                amount: Uint128::new(1u128),
+            },
+        ];
+
+        for msg in messages.iter() {
+            let execute_outcome = app.execute_contract(
+                Addr::unchecked(ADMIN),
+                contract_addr.clone(),
+                msg,
+                &[coin(1_000u128, JUNO_DENOM)],
+            );
+            assert_eq!(
+                execute_outcome.unwrap_err().source().unwrap().to_string(),
+                "NonPayable".to_string(),
+                "{:?}",
+                msg
+            );
+        }
+
+        // Nothing was kept by the contract
+        let contract_balance = app.wrap().query_balance(contract_addr, JUNO_DENOM).unwrap();
+        assert_eq!(contract_balance.amount, Uint128::zero());
+    }
+
+    #[test]
+    fn referrals() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+        let referrer = "juno1referrer";
+        let other_referrer = "juno1otherreferrer";
+        let referred_swap = |referrer: &str| ExecuteMsg::Swap {
+            product: None,
+            referrer: Some(referrer.to_string()),
+            lock_duration: None,
+            recipient: None,
+            forward_ibc: None,
+        };
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &referred_swap(referrer),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "ReferralDisabled".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateReferral {
+                referral: Referral {
+                    enabled: true,
+                    source: ReferralSource::DevFees,
+                    share_permille_u64: 500,
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateReferral {
+                referral: Referral {
+                    enabled: true,
+                    source: ReferralSource::DevFees,
+                    share_permille_u64: 1_001,
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidReferral".to_string()
+        );
+
+        // Half of the dev fees
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateReferral {
+                referral: Referral {
+                    enabled: true,
+                    source: ReferralSource::DevFees,
+                    share_permille_u64: 500,
+                },
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &referred_swap(WALLET1),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SelfReferral".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &referred_swap(referrer),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let referrer_balance = app.wrap().query_balance(referrer, JUNO_DENOM).unwrap();
+        assert_eq!(referrer_balance.amount, Uint128::new(10_000u128));
+        let dev_balance = app.wrap().query_balance(DEV, JUNO_DENOM).unwrap();
+        assert_eq!(dev_balance.amount, Uint128::new(10_000u128));
+        let stats_query: Statistics = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetStats {})
+            .unwrap();
+        assert_eq!(stats_query.dev_fees, Uint128::new(10_000u128));
+        assert_eq!(stats_query.referral_fees, Uint128::new(10_000u128));
+
+        // A tenth of the minted amount on top
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateReferral {
+                referral: Referral {
+                    enabled: true,
+                    source: ReferralSource::Mint,
+                    share_permille_u64: 100,
+                },
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &referred_swap(referrer),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let referrer_balance = app
+            .wrap()
+            .query_balance(referrer, "factory/contract0/balance")
+            .unwrap();
+        assert_eq!(referrer_balance.amount, Uint128::new(11_316u128));
+        let product_query: Product = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetProduct { denom: None })
+            .unwrap();
+        assert_eq!(
+            product_query.minted,
+            Uint128::new(113_169u128 * 2 + 11_316u128)
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &referred_swap(other_referrer),
+            &[coin(5_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let referrer_query: ReferrerStats = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetReferrer {
+                    address: referrer.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            referrer_query,
+            ReferrerStats {
+                referrer: Addr::unchecked(referrer),
+                swaps: 2,
+                referred: Uint128::new(2_000_000u128),
+                fees_earned: Uint128::new(10_000u128),
+                minted_earned: Uint128::new(11_316u128),
+            }
+        );
+
+        let top_referrers_query: Vec<ReferrerStats> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::ListTopReferrers { limit: None },
+            )
+            .unwrap();
+        assert_eq!(
+            top_referrers_query
+                .iter()
+                .map(|stats| stats.referrer.as_str())
+                .collect::<Vec<&str>>(),
+            vec![other_referrer, referrer]
+        );
+
+        let invariants_query: Vec<InvariantViolation> = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::CheckInvariants {})
+            .unwrap();
+        assert!(invariants_query.is_empty());
+    }
+
+    #[test]
+    fn vesting() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+        let denom = "factory/contract0/balance";
+        let tier = VestingTier {
+            min_amount: Uint128::new(1_000_000u128),
+            cliff: 100,
+            duration: 1_000,
+        };
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateVesting {
+                vesting: Vesting {
+                    enabled: true,
+                    tiers: vec![tier.clone(), tier.clone()],
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidVesting".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateVesting {
+                vesting: Vesting {
+                    enabled: true,
+                    tiers: vec![VestingTier {
+                        cliff: u64::MAX,
+                        ..tier.clone()
+                    }],
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidVesting".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateVesting {
+                vesting: Vesting {
+                    enabled: true,
+                    tiers: vec![tier.clone()],
+                },
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        // Below the first tier, minted right away
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(500_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        let wallet1_balance = app.wrap().query_balance(WALLET1, denom).unwrap();
+        assert_eq!(wallet1_balance.amount, Uint128::new(56_584u128));
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        let wallet1_balance = app.wrap().query_balance(WALLET1, denom).unwrap();
+        assert_eq!(wallet1_balance.amount, Uint128::new(56_584u128));
+
+        let start = app.block_info().time;
+        let positions_query: Vec<VestingPosition> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::ListVestingPositions {
+                    address: WALLET1.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            positions_query,
+            vec![VestingPosition {
+                id: 0,
+                owner: Addr::unchecked(WALLET1),
+                denom: denom.to_string(),
+                amount: Uint128::new(113_169u128),
+                claimed: Uint128::zero(),
+                start,
+                cliff: 100,
+                duration: 1_000,
+            }]
+        );
+
+        // Before the cliff
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::ClaimVested {},
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "NothingToClaim".to_string()
+        );
+
+        // Half way through the linear unlock
+        app.update_block(|block| block.time = block.time.plus_seconds(600));
+        let claimable_query: Vec<Coin> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetClaimableVested {
+                    address: WALLET1.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(claimable_query, vec![coin(56_584u128, denom)]);
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::ClaimVested {},
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let wallet1_balance = app.wrap().query_balance(WALLET1, denom).unwrap();
+        assert_eq!(wallet1_balance.amount, Uint128::new(113_168u128));
+
+        // The unclaimed part is still counted under the cap
+        let supply_query: SupplyInfo = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetSupplyInfo { denom: None },
+            )
+            .unwrap();
+        assert_eq!(supply_query.recorded, Uint128::new(113_168u128));
+        assert_eq!(supply_query.bank_supply, Uint128::new(113_168u128));
+        assert_eq!(
+            supply_query.remaining,
+            BALANCE_MAX_SUPPLY - Uint128::new(56_584u128 + 113_169u128)
+        );
+        let invariants_query: Vec<InvariantViolation> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::CheckInvariants {})
+            .unwrap();
+        assert!(invariants_query.is_empty());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::Pause {
+                operations: None,
+                reason: None,
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::ChangeDenomAdmin {
+                denom: None,
+                new_admin: ADMIN.to_string(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "VestingOutstanding - 56585".to_string()
+        );
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::Unpause { operations: None },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        // Fully unlocked, the position is removed
+        app.update_block(|block| block.time = block.time.plus_seconds(1_000));
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::ClaimVested {},
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let wallet1_balance = app.wrap().query_balance(WALLET1, denom).unwrap();
+        assert_eq!(
+            wallet1_balance.amount,
+            Uint128::new(56_584u128 + 113_169u128)
+        );
+        let positions_query: Vec<VestingPosition> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::ListVestingPositions {
+                    address: WALLET1.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert!(positions_query.is_empty());
+
+        // The tier follows the total swapped by the address
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(600_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        let admin_balance = app.wrap().query_balance(ADMIN, denom).unwrap();
+        assert_eq!(admin_balance.amount, Uint128::new(67_901u128));
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(600_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        let admin_balance = app.wrap().query_balance(ADMIN, denom).unwrap();
+        assert_eq!(admin_balance.amount, Uint128::new(67_901u128));
+        let positions_query: Vec<VestingPosition> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::ListVestingPositions {
+                    address: ADMIN.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(positions_query.len(), 1);
+        assert_eq!(positions_query[0].amount, Uint128::new(67_901u128));
+
+        // A lock shorter than the vesting doesn't skip it
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateLockup {
+                lockup: Lockup {
+                    enabled: true,
+                    bonuses: vec![
+                        LockBonus {
+                            duration: 100,
+                            bonus_permille_u64: 50,
+                        },
+                        LockBonus {
+                            duration: 1_100,
+                            bonus_permille_u64: 100,
+                        },
+                    ],
+                },
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let locked_swap = |lock_duration: u64| ExecuteMsg::Swap {
+            product: None,
+            referrer: None,
+            lock_duration: Some(lock_duration),
+            recipient: None,
+            forward_ibc: None,
+        };
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &locked_swap(500),
+            &[coin(600_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "LockShorterThanVesting - lock_duration: 500, vesting: 1100".to_string()
+        );
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &locked_swap(1_100),
+            &[coin(600_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        let locks_query: Vec<Lock> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr,
+                &QueryMsg::ListLocks {
+                    address: ADMIN.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(locks_query.len(), 1);
+    }
+
+    #[test]
+    fn lockup() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+        let denom = "factory/contract0/balance";
+        let ninety_days = 90 * 86_400;
+        let locked_swap = |lock_duration: u64| ExecuteMsg::Swap {
+            product: None,
+            referrer: None,
+            lock_duration: Some(lock_duration),
+            recipient: None,
+            forward_ibc: None,
+        };
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateLockup {
+                lockup: Lockup {
+                    enabled: true,
+                    bonuses: vec![LockBonus {
+                        duration: ninety_days,
+                        bonus_permille_u64: 1_001,
+                    }],
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidLockup".to_string()
+        );
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateLockup {
+                lockup: Lockup {
+                    enabled: true,
+                    bonuses: vec![LockBonus {
+                        duration: 0,
+                        bonus_permille_u64: 50,
+                    }],
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidLockup".to_string()
+        );
+
+        // +5% for 90 days, +10% for 180 days
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateLockup {
+                lockup: Lockup {
+                    enabled: true,
+                    bonuses: vec![
+                        LockBonus {
+                            duration: ninety_days,
+                            bonus_permille_u64: 50,
+                        },
+                        LockBonus {
+                            duration: 2 * ninety_days,
+                            bonus_permille_u64: 100,
+                        },
+                    ],
+                },
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &locked_swap(86_400),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "NoLockBonus - 86400".to_string()
+        );
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &locked_swap(u64::MAX),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            format!(
+                "LockTooLong - lock_duration: {}, longest: 15552000",
+                u64::MAX
+            )
+        );
+
+        let unlocks_at = app.block_info().time.plus_seconds(ninety_days + 86_400);
+        let simulation_query: SwapSimulation = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::SimulateSwap {
+                    amount: Uint128::new(1_000_000u128),
+                    product: None,
+                    lock_duration: Some(ninety_days + 86_400),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            simulation_query,
+            SwapSimulation {
+                amount_out: Uint128::new(118_827u128),
+                bonus: Uint128::new(5_658u128),
+                remaining: BALANCE_MAX_SUPPLY - Uint128::new(118_827u128),
+                unlocks_at: Some(unlocks_at),
+            }
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &locked_swap(ninety_days + 86_400),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        let wallet1_balance = app.wrap().query_balance(WALLET1, denom).unwrap();
+        assert_eq!(wallet1_balance.amount, Uint128::zero());
+
+        let locks_query: Vec<Lock> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::ListLocks {
+                    address: WALLET1.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            locks_query,
+            vec![Lock {
+                id: 0,
+                owner: Addr::unchecked(WALLET1),
+                denom: denom.to_string(),
+                amount: Uint128::new(118_827u128),
+                bonus: Uint128::new(5_658u128),
+                unlocks_at,
+            }]
+        );
+
+        // The locked amount is counted in the remaining supply
+        let simulation_query: SwapSimulation = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::SimulateSwap {
+                    amount: Uint128::new(1_000_000u128),
+                    product: None,
+                    lock_duration: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            simulation_query.remaining,
+            BALANCE_MAX_SUPPLY - Uint128::new(118_827u128 + 113_169u128)
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Withdraw {},
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "NothingToWithdraw".to_string()
+        );
+
+        app.update_block(|block| block.time = block.time.plus_seconds(ninety_days + 86_400));
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Withdraw {},
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let wallet1_balance = app.wrap().query_balance(WALLET1, denom).unwrap();
+        assert_eq!(wallet1_balance.amount, Uint128::new(118_827u128));
+
+        let locks_query: Vec<Lock> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::ListLocks {
+                    address: WALLET1.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert!(locks_query.is_empty());
+        let invariants_query: Vec<InvariantViolation> = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::CheckInvariants {})
+            .unwrap();
+        assert!(invariants_query.is_empty());
+    }
+
+    #[test]
+    fn redemption() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+        let denom = "factory/contract0/balance";
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Redeem {},
+            &[coin(10_000u128, denom)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "RedemptionClosed".to_string()
+        );
+
+        let now = app.block_info().time;
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateRedemption {
+                redemption: Redemption {
+                    enabled: true,
+                    starts_at: now,
+                    ends_at: now,
+                    rate: Decimal::from_ratio(8u128, 1u128),
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidRedemption".to_string()
+        );
+
+        // Paying more than the swap took
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateRedemption {
+                redemption: Redemption {
+                    enabled: true,
+                    starts_at: now,
+                    ends_at: now.plus_seconds(86_400),
+                    rate: Decimal::from_ratio(9u128, 1u128),
+                },
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidRedemption".to_string()
+        );
+
+        let redemption = Redemption {
+            enabled: true,
+            starts_at: now,
+            ends_at: now.plus_seconds(86_400),
+            rate: Decimal::from_ratio(8u128, 1u128),
+        };
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateRedemption {
+                redemption: redemption.clone(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let redemption_query: RedemptionStatus = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetRedemption {})
+            .unwrap();
+        assert_eq!(
+            redemption_query,
+            RedemptionStatus {
+                redemption: redemption.clone(),
+                reserve: Uint128::zero(),
+                required: Uint128::new(905_352u128),
+                sufficient: false,
+            }
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Redeem {},
+            &[coin(10_000u128, denom)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InsufficientReserve - reserve: 0, required: 80000".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::FundReserve {},
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let wallet1_juno = app.wrap().query_balance(WALLET1, JUNO_DENOM).unwrap();
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Redeem {},
+            &[coin(10_000u128, denom)],
+        );
+        assert!(execute_outcome.is_ok());
+
+        let wallet1_balance = app.wrap().query_balance(WALLET1, denom).unwrap();
+        assert_eq!(wallet1_balance.amount, Uint128::new(103_169u128));
+        let wallet1_juno_after = app.wrap().query_balance(WALLET1, JUNO_DENOM).unwrap();
+        assert_eq!(
+            wallet1_juno_after.amount - wallet1_juno.amount,
+            Uint128::new(80_000u128)
+        );
+        let contract_balance = app.wrap().query_balance(&contract_addr, denom).unwrap();
+        assert_eq!(contract_balance.amount, Uint128::zero());
+
+        let stats_query: Statistics = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetStats {})
+            .unwrap();
+        assert_eq!(stats_query.redeemed, Uint128::new(10_000u128));
+        assert_eq!(stats_query.redemption_payouts, Uint128::new(80_000u128));
+        let supply_query: SupplyInfo = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetSupplyInfo { denom: None },
+            )
+            .unwrap();
+        assert_eq!(supply_query.recorded, Uint128::new(103_169u128));
+        assert!(!supply_query.is_mismatch());
+
+        let redemption_query: RedemptionStatus = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetRedemption {})
+            .unwrap();
+        assert_eq!(redemption_query.reserve, Uint128::new(920_000u128));
+        assert_eq!(redemption_query.required, Uint128::new(825_352u128));
+        assert!(redemption_query.sufficient);
+
+        let invariants_query: Vec<InvariantViolation> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::CheckInvariants {})
+            .unwrap();
+        assert!(invariants_query.is_empty());
+
+        // Held by the contract once the denom admin is handed over
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::Pause {
+                operations: Some(vec![Operation::Swap]),
+                reason: None,
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::ChangeDenomAdmin {
+                denom: None,
+                new_admin: ADMIN.to_string(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Redeem {},
+            &[coin(10_000u128, denom)],
+        );
+        assert!(execute_outcome.is_ok());
+        let contract_balance = app.wrap().query_balance(&contract_addr, denom).unwrap();
+        assert_eq!(contract_balance.amount, Uint128::new(10_000u128));
+        let supply_query: SupplyInfo = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetSupplyInfo { denom: None },
+            )
+            .unwrap();
+        assert_eq!(supply_query.bank_supply, Uint128::new(103_169u128));
+        assert!(!supply_query.is_mismatch());
+        let recoverable_query: Vec<Coin> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetRecoverable {})
+            .unwrap();
+        assert!(recoverable_query.iter().all(|coin| coin.denom != denom));
+        let redemption_query: RedemptionStatus = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetRedemption {})
+            .unwrap();
+        assert_eq!(redemption_query.reserve, Uint128::new(840_000u128));
+        assert_eq!(redemption_query.required, Uint128::new(745_352u128));
+
+        // After the window
+        app.update_block(|block| block.time = block.time.plus_seconds(86_400));
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::Redeem {},
+            &[coin(10_000u128, denom)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "RedemptionClosed".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::WithdrawReserve {
+                amount: Uint128::new(840_000u128),
+                to: WALLET1.to_string(),
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::WithdrawReserve {
+                amount: Uint128::new(840_000u128),
+                to: ADMIN.to_string(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let redemption_query: RedemptionStatus = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::GetRedemption {})
+            .unwrap();
+        assert_eq!(redemption_query.reserve, Uint128::zero());
+    }
+
+    // CW20 with 10 tokens for WALLET1
+    fn instantiate_cw20(app: &mut TokenFactoryApp) -> Addr {
+        let cw20_id = app.store_code(Box::new(ContractWrapper::new_with_empty(
+            cw20_base::contract::execute,
+            cw20_base::contract::instantiate,
+            cw20_base::contract::query,
+        )));
+        app.instantiate_contract(
+            cw20_id,
+            Addr::unchecked(ADMIN),
+            &cw20_base::msg::InstantiateMsg {
+                name: "Other".to_string(),
+                symbol: "OTHER".to_string(),
+                decimals: 6,
+                initial_balances: vec![Cw20Coin {
+                    address: WALLET1.to_string(),
+                    amount: Uint128::new(10_000_000u128),
+                }],
+                mint: None,
+                marketing: None,
+            },
+            &[],
+            "other",
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn cw20_swaps() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+        let denom = "factory/contract0/balance";
+
+        let cw20_addr = instantiate_cw20(&mut app);
+        let send_msg = Cw20ExecuteMsg::Send {
+            contract: contract_addr.to_string(),
+            amount: Uint128::new(1_000_000u128),
+            msg: to_binary(&ReceiveMsg::Swap { product: None }).unwrap(),
+        };
+
+        let execute_outcome =
+            app.execute_contract(Addr::unchecked(WALLET1), cw20_addr.clone(), &send_msg, &[]);
+        assert_eq!(
+            execute_outcome.unwrap_err().root_cause().to_string(),
+            format!("Cw20NotAllowed - {}", cw20_addr)
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateCw20Allowlist {
+                add: vec![cw20_addr.to_string()],
+                remove: vec![],
+            },
+            &[],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "Unauthorized".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateCw20Allowlist {
+                add: vec![cw20_addr.to_string()],
+                remove: vec![],
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let allowlist_query: Vec<String> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetCw20Allowlist {})
+            .unwrap();
+        assert_eq!(allowlist_query, vec![cw20_addr.to_string()]);
+
+        let execute_outcome =
+            app.execute_contract(Addr::unchecked(WALLET1), cw20_addr.clone(), &send_msg, &[]);
+        assert!(execute_outcome.is_ok());
+        assert_eq!(
+            app.wrap().query_balance(WALLET1, denom).unwrap().amount,
+            Uint128::new(113_169u128)
+        );
+
+        // The burned share is burned on the CW20 contract
+        for (address, amount) in [
+            (WALLET1, 9_000_000u128),
+            (BAL_DEV_FUND, 100_000u128),
+            (JUNO_DEV_FUND, 100_000u128),
+            (DEV, 20_000u128),
+        ] {
+            let balance_query: BalanceResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    cw20_addr.clone(),
+                    &Cw20QueryMsg::Balance {
+                        address: address.to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(balance_query.balance, Uint128::new(amount));
+        }
+        let token_info_query: TokenInfoResponse = app
+            .wrap()
+            .query_wasm_smart(cw20_addr.clone(), &Cw20QueryMsg::TokenInfo {})
+            .unwrap();
+        assert_eq!(token_info_query.total_supply, Uint128::new(9_220_000u128));
+
+        let cw20_stats_query: Statistics = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetCw20Stats {
+                    token: cw20_addr.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(cw20_stats_query.received, Uint128::new(1_000_000u128));
+        assert_eq!(cw20_stats_query.burned, Uint128::new(780_000u128));
+        assert_eq!(cw20_stats_query.distributed, Uint128::new(113_169u128));
+        let stats_query: Statistics = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetStats {})
+            .unwrap();
+        assert_eq!(stats_query.received, Uint128::zero());
+        assert_eq!(stats_query.distributed, Uint128::new(113_169u128));
+
+        // The CW20 amounts count 1:1 towards the vesting tiers, as amounts of the accepted denom
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateVesting {
+                vesting: Vesting {
+                    enabled: true,
+                    tiers: vec![VestingTier {
+                        min_amount: Uint128::new(1_500_000u128),
+                        cliff: 100,
+                        duration: 1_000,
+                    }],
+                },
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(WALLET1),
+            cw20_addr.clone(),
+            &Cw20ExecuteMsg::Send {
+                contract: contract_addr.to_string(),
+                amount: Uint128::new(500_000u128),
+                msg: to_binary(&ReceiveMsg::Swap { product: None }).unwrap(),
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        assert_eq!(
+            app.wrap().query_balance(WALLET1, denom).unwrap().amount,
+            Uint128::new(113_169u128)
+        );
+        let positions_query: Vec<VestingPosition> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::ListVestingPositions {
+                    address: WALLET1.to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            positions_query,
+            vec![VestingPosition {
+                id: 0,
+                owner: Addr::unchecked(WALLET1),
+                denom: denom.to_string(),
+                amount: Uint128::new(56_584u128),
+                claimed: Uint128::zero(),
+                start: app.block_info().time,
+                cliff: 100,
+                duration: 1_000,
+            }]
+        );
+
+        let invariants_query: Vec<InvariantViolation> = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::CheckInvariants {})
+            .unwrap();
+        assert!(invariants_query.is_empty());
+    }
+
+    #[test]
+    fn cw20_swap_guards() {
+        let mut app = mock_app();
+        let contract_addr = setup(
+            &mut app,
+            InstantiateMsg {
+                swap_limits: Some(SwapLimits {
+                    per_tx_max: Some(Uint128::new(1_000_000u128)),
+                    per_address_max: Some(Uint128::new(1_500_000u128)),
+                    per_address_window: None,
+                }),
+                circuit_breaker: Some(CircuitBreaker {
+                    window: Duration::Time(3_600),
+                    max_volume: Uint128::new(2_000_000u128),
+                    guardian: None,
+                }),
+                ..init_msg()
+            },
+        );
+        let cw20_addr = instantiate_cw20(&mut app);
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateCw20Allowlist {
+                add: vec![cw20_addr.to_string()],
+                remove: vec![],
+            },
+            &[],
+        );
+        assert!(execute_outcome.is_ok());
+        let send = |app: &mut TokenFactoryApp, amount: u128| {
+            app.execute_contract(
+                Addr::unchecked(WALLET1),
+                cw20_addr.clone(),
+                &Cw20ExecuteMsg::Send {
+                    contract: contract_addr.to_string(),
+                    amount: Uint128::new(amount),
+                    msg: to_binary(&ReceiveMsg::Swap { product: None }).unwrap(),
+                },
+                &[],
+            )
+        };
+
+        // Same per transaction and per address limits as the native swaps
+        let execute_outcome = send(&mut app, 1_000_001u128);
+        assert_eq!(
+            execute_outcome.unwrap_err().root_cause().to_string(),
+            "SwapLimitPerTxExceeded - remaining: 1000000".to_string()
+        );
+        assert!(send(&mut app, 1_000_000u128).is_ok());
+        let execute_outcome = send(&mut app, 600_000u128);
+        assert_eq!(
+            execute_outcome.unwrap_err().root_cause().to_string(),
+            "SwapLimitPerAddressExceeded - remaining: 500000".to_string()
+        );
+
+        // The native and CW20 swaps fill the same window of the circuit breaker
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &swap_msg(),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        let execute_outcome = send(&mut app, 100_000u128);
+        assert!(execute_outcome
+            .unwrap()
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-circuit_breaker_tripped"));
+        // Refunded with a CW20 transfer
+        let balance_query: BalanceResponse = app
+            .wrap()
+            .query_wasm_smart(
+                cw20_addr.clone(),
+                &Cw20QueryMsg::Balance {
+                    address: WALLET1.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(balance_query.balance, Uint128::new(9_000_000u128));
+        let execute_outcome = send(&mut app, 100_000u128);
+        assert_eq!(
+            execute_outcome.unwrap_err().root_cause().to_string(),
+            "CircuitBreakerTripped".to_string()
+        );
+    }
+
+    #[test]
+    fn ibc_hooks() {
+        let mut app = mock_app();
+        let contract_addr = setup(&mut app, init_msg());
+        let denom = "factory/contract0/balance";
+
+        // Sender of the ibc-hooks memo from osmosis over channel-0
+        let intermediate_sender: String = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetIntermediateSender {
+                    channel: "channel-0".to_string(),
+                    original_sender: "osmo1qyqszqgpqyqszqgpqyqszqgpqyqszqgpjnp7du".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            intermediate_sender,
+            "juno1tn9ak504flmsqdyqkqmga3era69tdtjxsj5xud3jc8wdzgjndh0sysyjry".to_string()
+        );
+        app.sudo(SudoMsg::Bank(BankSudo::Mint {
+            to_address: intermediate_sender.clone(),
+            amount: vec![coin(10_000_000u128, JUNO_DENOM)],
+        }))
+        .unwrap();
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(&intermediate_sender),
+            contract_addr.clone(),
+            &ExecuteMsg::Swap {
+                product: None,
+                referrer: None,
+                lock_duration: None,
+                recipient: Some(WALLET1.to_string()),
+                forward_ibc: None,
+            },
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert!(execute_outcome.is_ok());
+        assert_eq!(
+            app.wrap().query_balance(WALLET1, denom).unwrap().amount,
+            Uint128::new(113_169u128)
+        );
+        assert_eq!(
+            app.wrap()
+                .query_balance(&intermediate_sender, denom)
+                .unwrap()
+                .amount,
+            Uint128::zero()
+        );
+
+        // Referring the recipient is a self referral
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(&intermediate_sender),
+            contract_addr.clone(),
+            &ExecuteMsg::Swap {
+                product: None,
+                referrer: Some(WALLET1.to_string()),
+                lock_duration: None,
+                recipient: Some(WALLET1.to_string()),
+                forward_ibc: None,
+            },
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "SelfReferral".to_string()
+        );
+
+        let forward_swap = |channel: &str| ExecuteMsg::Swap {
+            product: None,
+            referrer: None,
+            lock_duration: None,
+            recipient: Some(WALLET1.to_string()),
+            forward_ibc: Some(IbcForward {
+                channel: channel.to_string(),
+                receiver: "osmo1qyqszqgpqyqszqgpqyqszqgpqyqszqgpjnp7du".to_string(),
+                timeout: 600,
+            }),
+        };
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(&intermediate_sender),
+            contract_addr.clone(),
+            &forward_swap("transfer"),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "InvalidIbcForward".to_string()
+        );
+
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(&intermediate_sender),
+            contract_addr.clone(),
+            &ExecuteMsg::Swap {
+                product: None,
+                referrer: None,
+                lock_duration: None,
+                recipient: None,
+                forward_ibc: Some(IbcForward {
+                    channel: "channel-0".to_string(),
+                    receiver: "osmo1qyqszqgpqyqszqgpqyqszqgpqyqszqgpjnp7du".to_string(),
+                    timeout: 600,
+                }),
+            },
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        assert_eq!(
+            execute_outcome.unwrap_err().source().unwrap().to_string(),
+            "RecipientRequired".to_string()
+        );
+
+        // The mocked IBC fails the transfer, the product is refunded to the recipient
+        let execute_outcome = app.execute_contract(
+            Addr::unchecked(&intermediate_sender),
+            contract_addr.clone(),
+            &forward_swap("channel-0"),
+            &[coin(1_000_000u128, JUNO_DENOM)],
+        );
+        let response = execute_outcome.unwrap();
+        assert!(response
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-ibc_forward_refunded"));
+        assert_eq!(
+            app.wrap().query_balance(WALLET1, denom).unwrap().amount,
+            Uint128::new(226_338u128)
+        );
+        assert_eq!(
+            app.wrap()
+                .query_balance(&contract_addr, denom)
+                .unwrap()
+                .amount,
+            Uint128::zero()
+        );
+
+        let sudo_outcome = app.wasm_sudo(
+            contract_addr.clone(),
+            &ContractSudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout {
+                channel: "channel-0".to_string(),
+                sequence: 1,
+            }),
+        );
+        assert_eq!(
+            sudo_outcome.unwrap_err().root_cause().to_string(),
+            "UnknownIbcPacket - channel: channel-0, sequence: 1".to_string()
+        );
+
+        let invariants_query: Vec<InvariantViolation> = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::CheckInvariants {})
+            .unwrap();
+        assert!(invariants_query.is_empty());
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum ProtoField {
+        Varint(u64),
+        Bytes(Vec<u8>),
+    }
+
+    fn read_varint(bytes: &mut &[u8]) -> u64 {
+        let mut value = 0u64;
+        for i in 0.. {
+            let (byte, rest) = bytes.split_first().unwrap();
+            *bytes = rest;
+            value |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        value
+    }
+
+    // The fields of a protobuf message, in order
+    fn decode_proto(mut bytes: &[u8]) -> Vec<(u64, ProtoField)> {
+        let mut fields = vec![];
+        while !bytes.is_empty() {
+            let key = read_varint(&mut bytes);
+            let field = match key & 0x07 {
+                0 => ProtoField::Varint(read_varint(&mut bytes)),
+                2 => {
+                    let length = read_varint(&mut bytes) as usize;
+                    let (value, rest) = bytes.split_at(length);
+                    bytes = rest;
+                    ProtoField::Bytes(value.to_vec())
+                }
+                wire_type => panic!("unexpected wire type {}", wire_type),
+            };
+            fields.push((key >> 3, field));
+        }
+        fields
+    }
+
+    fn proto_bytes(field: u8, value: &[u8]) -> Vec<u8> {
+        [&[field << 3 | 2, value.len() as u8], value].concat()
+    }
+
+    #[test]
+    fn ibc_forward_lifecycle() {
+        let mut app = mock_app();
+        let contract_addr = setup_contract(&mut app, ibc_transfer_contract_box_def(), init_msg());
+        let denom = "factory/contract0/balance";
+        let intermediate_sender = "juno1tn9ak504flmsqdyqkqmga3era69tdtjxsj5xud3jc8wdzgjndh0sysyjry";
+        app.sudo(SudoMsg::Bank(BankSudo::Mint {
+            to_address: intermediate_sender.to_string(),
+            amount: vec![coin(10_000_000u128, JUNO_DENOM)],
+        }))
+        .unwrap();
+        let forward_ibc = IbcForward {
+            channel: "channel-0".to_string(),
+            receiver: "osmo1qyqszqgpqyqszqgpqyqszqgpqyqszqgpjnp7du".to_string(),
+            timeout: 600,
+        };
+
+        // A `MsgTransfer` with the ibc-hooks callback memo
+        let env = mock_env();
+        let sub_msg = crate::helpers::ibc_forward_submsg(
+            &mut MockStorage::new(),
+            &env,
+            &Addr::unchecked(WALLET1),
+            forward_ibc.clone(),
+            coin(113_169u128, denom),
+        )
+        .unwrap();
+        match sub_msg.msg {
+            CosmosMsg::Stargate { type_url, value } => {
+                assert_eq!(type_url, MSG_TRANSFER_TYPE_URL.to_string());
+                let token = [proto_bytes(1, denom.as_bytes()), proto_bytes(2, b"113169")].concat();
+                assert_eq!(
+                    decode_proto(&value),
+                    vec![
+                        (1, ProtoField::Bytes(b"transfer".to_vec())),
+                        (2, ProtoField::Bytes(b"channel-0".to_vec())),
+                        (3, ProtoField::Bytes(token)),
+                        (
+                            4,
+                            ProtoField::Bytes(env.contract.address.as_bytes().to_vec())
+                        ),
+                        (
+                            5,
+                            ProtoField::Bytes(forward_ibc.receiver.as_bytes().to_vec())
+                        ),
+                        (
+                            7,
+                            ProtoField::Varint(env.block.time.plus_seconds(600).nanos())
+                        ),
+                        (
+                            8,
+                            ProtoField::Bytes(
+                                format!(r#"{{"ibc_callback":"{}"}}"#, env.contract.address)
+                                    .into_bytes()
+                            )
+                        ),
+                    ]
+                );
+            }
+            msg => panic!("unexpected message {:?}", msg),
+        }
+
+        // Reverted without the sequence of the `MsgTransferResponse`
+        let mut deps = mock_dependencies();
+        crate::helpers::ibc_forward_submsg(
+            &mut deps.storage,
+            &env,
+            &Addr::unchecked(WALLET1),
+            forward_ibc.clone(),
+            coin(113_169u128, denom),
+        )
+        .unwrap();
+        let reply_outcome = crate::executes::ibc_forward_reply(
+            DepsMut {
+                storage: &mut deps.storage,
+                api: &deps.api,
+                querier: QuerierWrapper::new(&deps.querier),
+            },
+            SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        );
+        assert_eq!(
+            reply_outcome.unwrap_err().to_string(),
+            "InvalidTransferResponse".to_string()
+        );
+
+        let forward_swap = |app: &mut TokenFactoryApp| {
+            app.update_block(|block| block.height += 1);
+            let response = app
+                .execute_contract(
+                    Addr::unchecked(intermediate_sender),
+                    contract_addr.clone(),
+                    &ExecuteMsg::Swap {
+                        product: None,
+                        referrer: None,
+                        lock_duration: None,
+                        recipient: Some(WALLET1.to_string()),
+                        forward_ibc: Some(forward_ibc.clone()),
+                    },
+                    &[coin(1_000_000u128, JUNO_DENOM)],
+                )
+                .unwrap();
+            // Kept with the sequence of the `MsgTransferResponse`
+            let sequence = app.block_info().height;
+            assert!(response
+                .events
+                .iter()
+                .any(|event| event.ty == "wasm-ibc_forward"
+                    && event
+                        .attributes
+                        .iter()
+                        .any(|attr| attr.key == "sequence" && attr.value == sequence.to_string())));
+            sequence
+        };
+        let lifecycle_complete = |app: &mut TokenFactoryApp, lifecycle: IbcLifecycleComplete| {
+            app.wasm_sudo(
+                contract_addr.clone(),
+                &ContractSudoMsg::IbcLifecycleComplete(lifecycle),
+            )
+        };
+
+        // The failed acknowledgement refunds the recipient, once
+        let sequence = forward_swap(&mut app);
+        let failed_ack = IbcLifecycleComplete::IbcAck {
+            channel: "channel-0".to_string(),
+            sequence,
+            ack: "error".to_string(),
+            success: false,
+        };
+        let sudo_outcome = lifecycle_complete(&mut app, failed_ack.clone());
+        assert!(sudo_outcome
+            .unwrap()
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-ibc_forward_refunded"));
+        assert_eq!(
+            app.wrap().query_balance(WALLET1, denom).unwrap().amount,
+            Uint128::new(113_169u128)
+        );
+        let sudo_outcome = lifecycle_complete(&mut app, failed_ack);
+        assert_eq!(
+            sudo_outcome.unwrap_err().root_cause().to_string(),
+            format!(
+                "UnknownIbcPacket - channel: channel-0, sequence: {}",
+                sequence
+            )
+        );
+
+        // The timeout refunds the recipient
+        let sequence = forward_swap(&mut app);
+        let sudo_outcome = lifecycle_complete(
+            &mut app,
+            IbcLifecycleComplete::IbcTimeout {
+                channel: "channel-0".to_string(),
+                sequence,
+            },
+        );
+        assert!(sudo_outcome.is_ok());
+        assert_eq!(
+            app.wrap().query_balance(WALLET1, denom).unwrap().amount,
+            Uint128::new(226_338u128)
+        );
+
+        // Nothing is refunded once acknowledged
+        let sequence = forward_swap(&mut app);
+        let sudo_outcome = lifecycle_complete(
+            &mut app,
+            IbcLifecycleComplete::IbcAck {
+                channel: "channel-0".to_string(),
+                sequence,
+                ack: "AQ==".to_string(),
+                success: true,
+            },
+        );
+        assert!(sudo_outcome
+            .unwrap()
+            .events
+            .iter()
+            .any(|event| event.ty == "wasm-ibc_forward_acknowledged"));
+        assert_eq!(
+            app.wrap().query_balance(WALLET1, denom).unwrap().amount,
+            Uint128::new(226_338u128)
+        );
+    }
 }